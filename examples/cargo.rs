@@ -1,6 +1,6 @@
 extern crate space_toml;
 
-use space_toml::Table;
+use space_toml::TableData;
 use std::env;
 use std::process;
 use std::io::Read;
@@ -21,13 +21,13 @@ fn main() {
         // Ensure that we don't borrow the table for too long
         let mut dependencies = table.find_or_insert_table(&["dependencies"])
             .expect("Invalid file structure");
-        let mut dep = Table::new_inline();
+        let mut dep = TableData::new_inline();
         dep.insert("version", env!("CARGO_PKG_VERSION"));
-        dependencies.insert("space_toml", dep);
+        dependencies.insert_smart("space_toml", dep);
 
-        let mut dep = Table::new_inline();
+        let mut dep = TableData::new_inline();
         dep.insert("path", "../rsdl2");
-        dependencies.insert("rsdl2", dep);
+        dependencies.insert_smart("rsdl2", dep);
     }
 
     let mut output = String::new();
@@ -80,8 +80,8 @@ fn main() {
 
     env_logger::init().unwrap();
 
-    let table = match space_toml::parse(&toml) {
-        Ok(table) => table,
+    let mut document = match space_toml::parse(&toml) {
+        Ok(document) => document,
         Err(e) => {
             println!("Parsing failed:");
             println!("{:?}", e);
@@ -92,13 +92,13 @@ fn main() {
 
     println!("======= Output =======");
     let mut output = String::new();
-    table.write(&mut output);
+    document.write(&mut output);
     println!("{}", output);
 
     assert!(output == toml, "======== expected =======\n{}", toml);
 
     let json = Json::from_str(&json).expect("JSON parsing failed");
-    let toml_json = serialize_json(&table);
+    let toml_json = serialize_json(&document.root());
     assert!(json == toml_json,
             "expected\n{}\ngot\n{}\n",
             json.pretty(),
@@ -1,5 +1,6 @@
 
 use value::Value;
+use tabledata::TableData;
 use std::slice;
 
 /// A 'visual' item within a TOML array.
@@ -51,9 +52,21 @@ impl<'a> ArrayData<'a> {
         &self.items
     }
 
+    /// Pushes a value to the array without any formatting, and returns a reference
+    /// to it.
+    /// Errors if the value is of a different type than the first element of the array.
     pub fn push_value(&mut self, value: Value<'a>) -> Result<&mut Value<'a>, String> {
+        self.check_type(&value)?;
+        self.order.push(ArrayItem::Item);
+        self.items.push(value);
+        let index = self.items.len() - 1;
+        Ok(&mut self.items[index])
+    }
+
+    /// Errors if the value is of a different type than the first element of the array.
+    fn check_type(&self, value: &Value<'a>) -> Result<(), String> {
         if let Some(first) = self.items.get(0) {
-            if !first.is_same_type(&value) {
+            if !first.is_same_type(value) {
                 return Err(format!("Attempted to insert a value of type {:?} into an array of \
                                     type {:?}",
                                    value,
@@ -65,10 +78,7 @@ impl<'a> ArrayData<'a> {
             return Err(format!("Attempted to insert a value of type {:?} into an array of tables",
                                value));
         }
-        self.order.push(ArrayItem::Item);
-        self.items.push(value);
-        let index = self.items.len() - 1;
-        Ok(&mut self.items[index])
+        Ok(())
     }
 
     /// Pushes an amount of whitespace to the array format order.
@@ -86,16 +96,58 @@ impl<'a> ArrayData<'a> {
         self.order.push(ArrayItem::Comment(comment));
     }
 
+    /// Returns the number of values in this array.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns a reference to the value at the given index, if present.
+    pub fn get(&self, index: usize) -> Option<&Value<'a>> {
+        self.items.get(index)
+    }
+
+    /// Returns a mutable reference to the value at the given index, if present.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Value<'a>> {
+        self.items.get_mut(index)
+    }
+
     /// Returns an iterator over the items in this array.
     pub fn iter(&self) -> slice::Iter<Value<'a>> {
         self.items.iter()
     }
 
+    /// Returns an iterator over mutable references to the items in this array.
+    pub fn iter_mut(&mut self) -> slice::IterMut<Value<'a>> {
+        self.items.iter_mut()
+    }
+
+    /// Returns the values of this array, leaving out its formatting.
+    pub fn into_values(self) -> Vec<Value<'a>> {
+        self.items
+    }
+
     /// Returns whether this array is empty of values (it might still contain formatting info).
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
 
+    /// Returns whether this array is written across several lines, that is whether there
+    /// is a newline between its brackets, outside of its values.
+    pub fn is_multiline(&self) -> bool {
+        self.order.iter().any(|item| match *item {
+            ArrayItem::Space(text) => text.contains('\n'),
+            _ => false,
+        })
+    }
+
+    /// Returns whether there are comments between the values of this array.
+    pub fn has_comments(&self) -> bool {
+        self.order.iter().any(|item| match *item {
+            ArrayItem::Comment(_) => true,
+            _ => false,
+        })
+    }
+
     /// Returns the last element of this array.
     pub fn last(&mut self) -> Option<&mut Value<'a>> {
         if self.is_empty() {
@@ -132,13 +184,85 @@ impl<'a> ArrayData<'a> {
     /// TODO: This should be split into an internal and external function.
     pub fn push<V: Into<Value<'a>>>(&mut self, value: V) -> Result<&mut Value<'a>, String> {
         let value = value.into();
-        if self.is_inline && !self.has_trailing_comma() {
+        self.check_type(&value)?;
+        if self.is_inline && !self.is_empty() && !self.has_trailing_comma() {
             self.push_comma();
             self.push_space(" ");
         }
         self.push_value(value)
     }
 
+    /// Replaces the table element whose value at `key_field` equals that of the given
+    /// table, or else pushes the table to the array.
+    /// Regular tables keep the rest of their header line and their trailing blank lines.
+    /// Errors if the table can't be pushed to the array.
+    pub fn upsert_table_by(&mut self, key_field: &str, table: TableData<'a>) -> Result<(), String> {
+        let index = table.get(key_field).and_then(|wanted| {
+            self.items.iter().position(|item| match *item {
+                Value::Table(ref other) => other.get(key_field) == Some(wanted),
+                _ => false,
+            })
+        });
+        match index {
+            Some(index) => {
+                match self.items[index] {
+                    Value::Table(ref mut old) if !old.is_inline() && !table.is_inline() => {
+                        old.replace_contents(table);
+                    }
+                    ref mut old => *old = Value::Table(table),
+                }
+                Ok(())
+            }
+            None => self.push(table).map(|_| ()),
+        }
+    }
+
+    /// Returns the width of this array when written on a single line as
+    /// `[a, b, c]`.
+    pub fn single_line_width(&self) -> usize {
+        let mut out = String::new();
+        for value in &self.items {
+            value.write(&mut out);
+        }
+        let separators = if self.items.is_empty() { 0 } else { 2 * (self.items.len() - 1) };
+        out.chars().count() + separators + 2
+    }
+
+    /// Lays out this array with one value per line, each indented by the given text
+    /// and followed by a comma. Comments are kept after the value before them.
+    pub fn wrap(&mut self, indent: &'a str) {
+        use self::ArrayItem::*;
+        let mut order = Vec::new();
+        for item in self.order.drain(..) {
+            match item {
+                Item => {
+                    order.push(Space("\n"));
+                    order.push(Space(indent));
+                    order.push(Item);
+                    order.push(Comma);
+                }
+                Comment(text) => {
+                    order.push(Space(" "));
+                    order.push(Comment(text));
+                }
+                Space(_) | Comma => {}
+            }
+        }
+        order.push(Space("\n"));
+        self.order = order;
+    }
+
+    /// Wraps this inline array like `wrap` if its single-line width would be more than
+    /// the given number of columns. Returns whether the array was wrapped.
+    pub fn wrap_if_wider(&mut self, max_width: usize, indent: &'a str) -> bool {
+        if self.is_inline && self.single_line_width() > max_width {
+            self.wrap(indent);
+            true
+        } else {
+            false
+        }
+    }
+
     /// Writes this TOML value to a string.
     pub fn write(&self, out: &mut String) {
         use self::ArrayItem::*;
@@ -165,3 +289,10 @@ impl<'a> ArrayData<'a> {
         }
     }
 }
+
+impl<'a> Default for ArrayData<'a> {
+    /// Creates an empty inline array.
+    fn default() -> ArrayData<'a> {
+        ArrayData::new_inline()
+    }
+}
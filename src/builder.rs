@@ -0,0 +1,156 @@
+use document::{Document, DocumentPrivate, Newline};
+use table::TablePrivate;
+use tabledata::TableData;
+use array::ArrayData;
+use key::Key;
+use value::Value;
+
+/// A sub-table of a table builder, which is written under a header of its own.
+enum Section<'src> {
+    Table(TableBuilder<'src>),
+    ArrayOfTables(Vec<TableBuilder<'src>>),
+}
+
+/// Builds a nested table in code, by chaining the entries and sub-tables to add.
+/// When built as a document, the entries are written one per line, and the sub-tables
+/// and arrays of tables are written under headers in the order they were added,
+/// separated by blank lines.
+pub struct TableBuilder<'src> {
+    entries: Vec<(Key<'src>, Value<'src>)>,
+    sections: Vec<(Key<'src>, Section<'src>)>,
+}
+
+impl<'src> TableBuilder<'src> {
+    /// Creates a builder for an empty table.
+    pub fn new() -> TableBuilder<'src> {
+        TableBuilder {
+            entries: Vec::new(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Adds an entry with the given key and value.
+    pub fn key<K, V>(mut self, key: K, value: V) -> TableBuilder<'src>
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        self.entries.push((key.into(), value.into()));
+        self
+    }
+
+    /// Adds a sub-table with the given key, built by the given function.
+    pub fn table<K, F>(mut self, key: K, f: F) -> TableBuilder<'src>
+        where K: Into<Key<'src>>,
+              F: FnOnce(TableBuilder<'src>) -> TableBuilder<'src>
+    {
+        self.sections.push((key.into(), Section::Table(f(TableBuilder::new()))));
+        self
+    }
+
+    /// Adds an array of tables with the given key, whose elements are pushed by the
+    /// given function.
+    pub fn array_of_tables<K, F>(mut self, key: K, f: F) -> TableBuilder<'src>
+        where K: Into<Key<'src>>,
+              F: FnOnce(&mut Vec<TableBuilder<'src>>)
+    {
+        let mut elements = Vec::new();
+        f(&mut elements);
+        self.sections.push((key.into(), Section::ArrayOfTables(elements)));
+        self
+    }
+
+    /// Returns a regular table holding the entries of this builder, with the sub-tables
+    /// and arrays of tables as values that aren't written as entries.
+    pub fn build(mut self) -> TableData<'src> {
+        let mut table = self.entries_table();
+        for (key, section) in self.sections {
+            let value = match section {
+                Section::Table(builder) => Value::Table(builder.build()),
+                Section::ArrayOfTables(builders) => {
+                    let mut array = ArrayData::new_of_tables();
+                    for builder in builders {
+                        array.push_value(Value::Table(builder.build()))
+                            .expect("The elements are all tables");
+                    }
+                    Value::Array(array)
+                }
+            };
+            table.items.insert(key, value);
+        }
+        table
+    }
+
+    /// Returns a document holding the entries of this builder, followed by its
+    /// sub-tables and arrays of tables under their headers.
+    pub fn build_document(self) -> Document<'src> {
+        let mut document = Document::new();
+        let mut has_content = !self.entries.is_empty();
+        {
+            let mut root = document.root();
+            for (key, value) in self.entries {
+                root.insert_smart(key, value);
+            }
+        }
+        write_sections(self.sections, &mut document, &mut Vec::new(), &mut has_content);
+        document
+    }
+
+    /// Returns a regular table holding only the entries of this builder, one per line.
+    fn entries_table(&mut self) -> TableData<'src> {
+        let mut table = TableData::new_regular();
+        table.ensure_newline_after_scope();
+        for (key, value) in self.entries.drain(..) {
+            table.insert(key, value);
+        }
+        table
+    }
+}
+
+impl<'src> Default for TableBuilder<'src> {
+    /// Creates a builder for an empty table.
+    fn default() -> TableBuilder<'src> {
+        TableBuilder::new()
+    }
+}
+
+/// Adds the given sections below the given path to the document, each under a header
+/// that is separated from the content before it by a blank line.
+fn write_sections<'src>(sections: Vec<(Key<'src>, Section<'src>)>,
+                        document: &mut Document<'src>,
+                        path: &mut Vec<Key<'src>>,
+                        has_content: &mut bool) {
+    for (key, section) in sections {
+        path.push(key);
+        let builders = match section {
+            Section::Table(builder) => vec![(false, builder)],
+            Section::ArrayOfTables(builders) => builders.into_iter().map(|b| (true, b)).collect(),
+        };
+        for (is_array, mut builder) in builders {
+            if *has_content {
+                document.push_newline(Newline::Lf);
+            }
+            *has_content = true;
+            let table = builder.entries_table();
+            if is_array {
+                document.push_array_scope_unchecked(path.iter().collect());
+                let (last, rest) = path.split_last().unwrap();
+                let mut parent = if rest.is_empty() {
+                    document.root()
+                } else {
+                    document.find_or_insert_table_unscoped(rest).expect("The path holds tables")
+                };
+                let value = parent.get_or_insert_with(*last, || ArrayData::new_of_tables().into());
+                if let Value::Array(ref mut array) = *value {
+                    array.push_value(Value::Table(table)).expect("The elements are all tables");
+                }
+            } else {
+                document.push_table_scope_unchecked(path.iter().collect());
+                let mut parent = document.find_or_insert_table_unscoped(path)
+                    .expect("The path holds tables");
+                parent.data().replace_contents(table);
+            }
+            write_sections(builder.sections, document, path, has_content);
+        }
+        path.pop();
+    }
+}
@@ -0,0 +1,210 @@
+use std::str::Bytes;
+use std::iter::Peekable;
+
+/// A calendar date.
+/// example: `1979-05-27`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    /// The year (0-9999).
+    pub year: u16,
+    /// The month (1-12).
+    pub month: u8,
+    /// The day of the month (1-31).
+    pub day: u8,
+}
+
+/// A time of day.
+/// example: `07:32:00.999999`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    /// The hour (0-23).
+    pub hour: u8,
+    /// The minute (0-59).
+    pub minute: u8,
+    /// The second (0-60, to allow for leap seconds).
+    pub second: u8,
+    /// The fractional part of the second in nanoseconds.
+    /// Any digits beyond nanosecond precision are truncated.
+    pub nanosecond: u32,
+}
+
+/// The offset of a datetime from UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offset {
+    /// `Z`, that is UTC.
+    Z,
+    /// An offset like `-07:00`.
+    Custom {
+        /// The offset in minutes.
+        minutes: i16,
+    },
+}
+
+/// A structured view of a TOML datetime.
+/// Each part is optional, so that local datetimes and dates can be represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Datetime {
+    /// The date part, if present.
+    pub date: Option<Date>,
+    /// The time part, if present.
+    pub time: Option<Time>,
+    /// The offset from UTC, if present.
+    pub offset: Option<Offset>,
+}
+
+/// Reads exactly `count` ASCII digits as a number.
+fn read_digits(bytes: &mut Peekable<Bytes>, count: usize) -> Option<u32> {
+    let mut value = 0;
+    for _ in 0..count {
+        match bytes.next() {
+            Some(b @ b'0'..=b'9') => value = value * 10 + (b - b'0') as u32,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Reads the given byte.
+fn read_byte(bytes: &mut Peekable<Bytes>, expected: u8) -> Option<()> {
+    if bytes.next() == Some(expected) {
+        Some(())
+    } else {
+        None
+    }
+}
+
+/// Returns the number of days in the given month.
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Reads a `YYYY-MM-DD` date.
+fn read_date(bytes: &mut Peekable<Bytes>) -> Option<Date> {
+    let year = read_digits(bytes, 4)?;
+    read_byte(bytes, b'-')?;
+    let month = read_digits(bytes, 2)?;
+    read_byte(bytes, b'-')?;
+    let day = read_digits(bytes, 2)?;
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(Date {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+    })
+}
+
+/// Reads a `HH:MM:SS` time with optional fractional seconds.
+fn read_time(bytes: &mut Peekable<Bytes>) -> Option<Time> {
+    let hour = read_digits(bytes, 2)?;
+    read_byte(bytes, b':')?;
+    let minute = read_digits(bytes, 2)?;
+    read_byte(bytes, b':')?;
+    let second = read_digits(bytes, 2)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let mut nanosecond = 0;
+    if bytes.peek() == Some(&b'.') {
+        bytes.next();
+        let mut digits = 0;
+        while let Some(&b) = bytes.peek() {
+            match b {
+                b'0'..=b'9' => {
+                    // Digits beyond nanosecond precision are only kept in the text
+                    if digits < 9 {
+                        nanosecond = nanosecond * 10 + (b - b'0') as u32;
+                    }
+                    digits += 1;
+                    bytes.next();
+                }
+                _ => break,
+            }
+        }
+        if digits == 0 {
+            return None;
+        }
+        for _ in digits..9 {
+            nanosecond *= 10;
+        }
+    }
+    Some(Time {
+        hour: hour as u8,
+        minute: minute as u8,
+        second: second as u8,
+        nanosecond: nanosecond,
+    })
+}
+
+/// Reads a `Z` or `+HH:MM` offset.
+fn read_offset(bytes: &mut Peekable<Bytes>) -> Option<Offset> {
+    let sign = match bytes.next() {
+        Some(b'Z') | Some(b'z') => return Some(Offset::Z),
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return None,
+    };
+    let hours = read_digits(bytes, 2)?;
+    read_byte(bytes, b':')?;
+    let minutes = read_digits(bytes, 2)?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(Offset::Custom { minutes: sign * (hours * 60 + minutes) as i16 })
+}
+
+/// Parses the text of a TOML datetime, or returns the byte index of the date, time or
+/// offset part that is invalid, or of the text after a valid datetime.
+pub fn parse_datetime(text: &str) -> Result<Datetime, usize> {
+    let mut bytes = text.bytes().peekable();
+    let is_date = text.len() > 4 && text.as_bytes()[4] == b'-';
+    let date = if is_date {
+        Some(read_date(&mut bytes).ok_or(0usize)?)
+    } else {
+        None
+    };
+    let has_time = match bytes.peek() {
+        Some(&b'T') | Some(&b't') | Some(&b' ') if date.is_some() => {
+            bytes.next();
+            true
+        }
+        Some(_) => date.is_none(),
+        None => false,
+    };
+    let time = if has_time {
+        let pos = text.len() - bytes.len();
+        Some(read_time(&mut bytes).ok_or(pos)?)
+    } else {
+        None
+    };
+    let offset = if time.is_some() && date.is_some() && bytes.peek().is_some() {
+        let pos = text.len() - bytes.len();
+        Some(read_offset(&mut bytes).ok_or(pos)?)
+    } else {
+        None
+    };
+    if bytes.peek().is_some() {
+        return Err(text.len() - bytes.len());
+    }
+    Ok(Datetime {
+        date: date,
+        time: time,
+        offset: offset,
+    })
+}
+
+impl Datetime {
+    /// Parses the text of a TOML datetime.
+    /// Fractional seconds with more than nanosecond precision are truncated, so use
+    /// the text of the value to keep every digit.
+    /// Returns `None` if the text isn't a valid datetime.
+    pub fn parse(text: &str) -> Option<Datetime> {
+        parse_datetime(text).ok()
+    }
+}
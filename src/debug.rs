@@ -2,6 +2,7 @@
 
 use std::fmt;
 use std::io;
+use std::ops::Range;
 
 /// Returns a 1-indexed line/column pair from a text offset.
 pub fn get_position(text: &str, byte_offset: usize) -> (usize, usize) {
@@ -20,10 +21,47 @@ pub fn get_position(text: &str, byte_offset: usize) -> (usize, usize) {
     (line, col)
 }
 
+/// An index of where the lines of a text start, used to find the line/column pairs of
+/// many offsets in the same text, without going through the text before each of them.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    /// Creates an index of the lines of the given text.
+    pub fn new(text: &'a str) -> LineIndex<'a> {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex {
+            text: text,
+            line_starts: line_starts,
+        }
+    }
+
+    /// Returns the same 1-indexed line/column pair as `get_position`, only going through
+    /// the line of the offset.
+    pub fn position(&self, byte_offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&byte_offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let col = self.text[self.line_starts[line]..byte_offset].chars().count() + 1;
+        (line + 1, col)
+    }
+}
+
+/// Returns the byte range of the character at the given text offset, which is empty at
+/// the end of the text.
+pub fn char_span(text: &str, pos: usize) -> Range<usize> {
+    let len = text[pos..].chars().next().map_or(0, char::len_utf8);
+    pos..pos + len
+}
+
 /// Shows an unclosed delimiter in the source text.
 pub fn write_unclosed<O: fmt::Write>(text: &str, start: usize, output: &mut O) -> fmt::Result {
     let (line, col) = get_position(text, start);
-    let line_text = text.lines().skip(line - 1).next().unwrap();
+    let line_text = text.lines().skip(line - 1).next().unwrap_or("");
     writeln!(output, "{}", line_text)?;
     let line_len = line_text.chars().count();
     for _ in 0..col - 1 {
@@ -52,7 +90,7 @@ pub fn write_invalid_character<O: fmt::Write>(text: &str,
                                               output: &mut O)
                                               -> fmt::Result {
     let (line, col) = get_position(text, pos);
-    let line_text = text.lines().skip(line - 1).next().unwrap();
+    let line_text = text.lines().skip(line - 1).next().unwrap_or("");
     writeln!(output, "{}", line_text)?;
     for _ in 0..col - 1 {
         write!(output, " ")?;
@@ -1,26 +1,57 @@
-
-use tabledata::TableData;
+use tabledata::{self, TableData, TableItem};
 use table::{Table, TablePrivate};
 use scope::Scope;
+use schema::{Schema, SchemaError};
+use trivia::{self, Trivia, Attachment, Event};
 use key::Key;
-use value::Value;
+use value::{Value, TomlData, TomlType, StringStyle};
+use array::ArrayData;
+use parse::{self, Error};
 use std::iter::IntoIterator;
-use std::collections::hash_map;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::fmt;
+use std::ops::Range;
 
 /// An error found when creating or following a table path.
 #[derive(Debug)]
 pub enum InsertTableError {
+    /// The value at this key of the path is not a table.
     PathItemNotTable(String),
+    /// The given path is empty.
     EmptyPath,
 }
 
+/// An error found when resolving the includes of a document.
+#[derive(Debug)]
+pub enum IncludeError<'src> {
+    /// The include entry isn't a string or an array of strings.
+    InvalidValue,
+    /// No text was loaded for this included name.
+    NotFound(String),
+    /// This name is included by itself, directly or through other includes.
+    Cycle(String),
+    /// An included text couldn't be parsed.
+    Parse(Error<'src>),
+}
+
 /// A line-separating text sequence.
 #[derive(Debug, Clone, Copy)]
 pub enum Newline {
     /// '\n'
     Lf,
     /// '\r\n'
-    CrLf, 
+    CrLf,
+}
+
+impl Newline {
+    /// Returns the text of this newline.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
 }
 
 pub enum DocumentItem<'src> {
@@ -31,12 +62,406 @@ pub enum DocumentItem<'src> {
     ArrayScope(Scope<'src>),
 }
 
+/// A part of the path to a value in a document.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathPart {
+    /// The key of a table entry.
+    Key(String),
+    /// The index of a table in an array of tables.
+    Index(usize),
+}
+
+impl PathPart {
+    /// Formats the given path like `servers[0].ip`.
+    pub fn join(path: &[PathPart]) -> String {
+        let mut out = String::new();
+        for part in path {
+            if let PathPart::Key(_) = *part {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+            }
+            out.push_str(&part.to_string());
+        }
+        out
+    }
+}
+
+impl fmt::Display for PathPart {
+    /// Writes the part as it would appear in a path like `servers[0].ip`, without the
+    /// separating dot.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathPart::Key(ref key) => f.write_str(key),
+            PathPart::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+/// A semantic difference between two documents, found at a key path.
+#[derive(Debug)]
+pub enum Change<'a, 'src: 'a> {
+    /// A value was added at this path.
+    Added(Vec<PathPart>),
+    /// The value at this path was removed.
+    Removed(Vec<PathPart>),
+    /// The value at this path was changed from the first to the second value.
+    Modified(Vec<PathPart>, &'a Value<'src>, &'a Value<'src>),
+}
+
+/// Adds the paths and values of the leaves (non-table values) of the given table.
+/// The tables in arrays of tables are descended into with an index in their path.
+fn collect_leaves<'a, 'src>(table: &'a TableData<'src>,
+                            path: &mut Vec<PathPart>,
+                            leaves: &mut BTreeMap<Vec<PathPart>, &'a Value<'src>>) {
+    for (key, value) in table.iter() {
+        path.push(PathPart::Key(key.to_string()));
+        match *value {
+            Value::Table(ref table) => collect_leaves(table, path, leaves),
+            Value::Array(ref array) if !array.is_inline() => {
+                for (index, table) in array.iter().filter_map(Value::table).enumerate() {
+                    path.push(PathPart::Index(index));
+                    collect_leaves(table, path, leaves);
+                    path.pop();
+                }
+            }
+            ref value => {
+                leaves.insert(path.clone(), value);
+            }
+        }
+        path.pop();
+    }
+}
+
+/// Adds the paths of the values of the given type in the given value, including itself.
+/// Tables and arrays are descended into, with an index in the path for array elements.
+fn collect_values<'a, 'src>(value: &'a Value<'src>,
+                            ty: TomlType,
+                            path: &mut Vec<PathPart>,
+                            values: &mut BTreeMap<Vec<PathPart>, &'a Value<'src>>) {
+    if value.toml_type() == ty {
+        values.insert(path.clone(), value);
+    }
+    match *value {
+        Value::Table(ref table) => {
+            for (key, value) in table.iter() {
+                path.push(PathPart::Key(key.to_string()));
+                collect_values(value, ty, path, values);
+                path.pop();
+            }
+        }
+        Value::Array(ref array) => {
+            for (index, value) in array.iter().enumerate() {
+                path.push(PathPart::Index(index));
+                collect_values(value, ty, path, values);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Writes a table under a new header at the end of the document. Tables in an array of
+/// tables are written with their index in the array.
+fn write_section<'src, F>(path: &[Key<'src>],
+                              table: &TableData<'src>,
+                              index: Option<usize>,
+                              f: &mut F,
+                              out: &mut String)
+    where F: FnMut(&[PathPart], &Value<'src>, &mut String) -> bool
+{
+    if !out.is_empty() {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    path.iter().collect::<Scope>().write(out, index.is_some());
+    let mut parts = path.iter().map(|key| PathPart::Key(key.to_string())).collect::<Vec<_>>();
+    parts.extend(index.map(PathPart::Index));
+    tabledata::write_with(table, &mut parts, f, out);
+}
+
+/// Writes the regular tables below the given table that have entries, but no header in
+/// the document, under a new header at the end of the document. This happens when a
+/// table without a header of its own is edited through `Value::table_mut`.
+/// Tables pushed to an array of tables after its last header are written the same way,
+/// but arrays of tables are not descended into.
+fn write_unscoped_tables<'src, F>(table: &TableData<'src>,
+                                      path: &mut Vec<Key<'src>>,
+                                      scoped: &HashSet<Vec<Key<'src>>>,
+                                      array_scopes: &HashMap<Vec<Key<'src>>, usize>,
+                                      f: &mut F,
+                                      out: &mut String)
+    where F: FnMut(&[PathPart], &Value<'src>, &mut String) -> bool
+{
+    let mut keys = table.items.keys().collect::<Vec<_>>();
+    keys.sort_by_key(|key| key.normalized());
+    for key in keys {
+        path.push(*key);
+        match table.items[key] {
+            Value::Table(ref table) if !table.is_inline() => {
+                if !scoped.contains(path) && table.has_written_entries() {
+                    write_section(path, table, None, f, out);
+                }
+                write_unscoped_tables(table, path, scoped, array_scopes, f, out);
+            }
+            Value::Array(ref array) if !array.is_inline() => {
+                let written = array_scopes.get(path).cloned().unwrap_or(0);
+                for (index, value) in array.iter().enumerate().skip(written) {
+                    if let Value::Table(ref table) = *value {
+                        write_section(path, table, Some(index), f, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+/// Returns the path of a table with the given key path and array-of-tables indices.
+fn indexed_path(path: &[Key], indices: &[Option<usize>]) -> Vec<PathPart> {
+    let mut parts = Vec::new();
+    for (key, index) in path.iter().zip(indices) {
+        parts.push(PathPart::Key(key.to_string()));
+        if let Some(index) = *index {
+            parts.push(PathPart::Index(index));
+        }
+    }
+    parts
+}
+
+/// Adds the formatting items of the given table to the events.
+fn push_table_events<'src>(table: &TableData<'src>,
+                           path: &[PathPart],
+                           events: &mut Vec<Event<'src>>) {
+    for item in &table.order {
+        events.push(match *item {
+            TableItem::Space(_) | TableItem::Comma => Event::Space,
+            TableItem::Newline(_) => Event::Newline,
+            TableItem::Comment(text) => Event::Comment(text),
+            TableItem::Entry { ref key, .. } if table.items.contains_key(key) => {
+                let mut path = path.to_vec();
+                path.push(PathPart::Key(key.to_string()));
+                Event::Entry(path)
+            }
+            TableItem::Entry { .. } => continue,
+        });
+    }
+}
+
+/// The array-of-tables element indices used to find the table of a scope.
+/// `None` means that the last element is used.
+type ScopeIndices = Vec<Option<usize>>;
+
+/// Returns the table at the given path, using the given indices to select the elements
+/// of the arrays of tables along it.
+fn table_at<'a, 'src>(mut table: &'a TableData<'src>,
+                      path: &[Key<'src>],
+                      indices: &[Option<usize>])
+                      -> Option<&'a TableData<'src>> {
+    for (key, index) in path.iter().zip(indices) {
+        let current = table;
+        table = match current.items.get(key) {
+            Some(&Value::Table(ref table)) => table,
+            Some(&Value::Array(ref array)) if !array.is_inline() && !array.is_empty() => {
+                match array.get(index.unwrap_or(array.len() - 1)) {
+                    Some(&Value::Table(ref table)) => table,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+    }
+    Some(table)
+}
+
+/// Returns a mutable reference to the table at the given path, using the given indices
+/// to select the elements of the arrays of tables along it.
+fn table_at_mut<'a, 'src>(mut table: &'a mut TableData<'src>,
+                          path: &[Key<'src>],
+                          indices: &[Option<usize>])
+                          -> Option<&'a mut TableData<'src>> {
+    for (key, index) in path.iter().zip(indices) {
+        let current = table;
+        table = match current.items.get_mut(key) {
+            Some(&mut Value::Table(ref mut table)) => table,
+            Some(&mut Value::Array(ref mut array)) if !array.is_inline() && !array.is_empty() => {
+                let index = index.unwrap_or(array.len() - 1);
+                match array.get_mut(index) {
+                    Some(&mut Value::Table(ref mut table)) => table,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+    }
+    Some(table)
+}
+
+/// Returns a mutable reference to the value at the given key path, descending through
+/// tables. The keys are compared by their normalized text.
+fn value_at_mut<'a, 'src>(table: &'a mut TableData<'src>,
+                          path: &[String])
+                          -> Option<&'a mut Value<'src>> {
+    let (first, rest) = path.split_first()?;
+    let value = table.iter_mut().find(|&(key, _)| key.normalized() == first.as_str())?.1;
+    if rest.is_empty() {
+        return Some(value);
+    }
+    match *value {
+        Value::Table(ref mut table) => value_at_mut(table, rest),
+        _ => None,
+    }
+}
+
+/// Removes the table and array of tables headers of the document order that match the
+/// given predicate, which gets their index in the order, along with the newline before
+/// each of them.
+fn remove_headers<'src, F>(order: &mut Vec<DocumentItem<'src>>, mut is_removed: F)
+    where F: FnMut(usize, &Scope<'src>) -> bool
+{
+    let mut removed = vec![false; order.len()];
+    for (i, item) in order.iter().enumerate() {
+        match *item {
+            DocumentItem::Table(ref scope) |
+            DocumentItem::ArrayScope(ref scope) if is_removed(i, scope) => {
+                removed[i] = true;
+                if i > 0 {
+                    if let DocumentItem::Newline(_) = order[i - 1] {
+                        removed[i - 1] = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut index = 0;
+    order.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+}
+
+/// Returns the keys of the given table in the order their entries are written, followed
+/// by the keys without entries, like those of sub-tables, sorted.
+fn ordered_keys<'src>(table: &TableData<'src>) -> Vec<Key<'src>> {
+    let mut keys = table.order
+        .iter()
+        .filter_map(|item| match *item {
+            TableItem::Entry { key, .. } => Some(key),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut rest =
+        table.items.keys().filter(|key| !keys.contains(key)).cloned().collect::<Vec<_>>();
+    rest.sort_by_key(|key| key.normalized());
+    keys.extend(rest);
+    keys
+}
+
+/// Converts the given regular table, with its sub-tables and arrays of tables, into an
+/// inline table. The entries keep their order, followed by the sub-tables sorted by key,
+/// while the comments and blank lines of the table are left out.
+fn into_inline_table(table: TableData) -> TableData {
+    let keys = ordered_keys(&table);
+    let mut items = table.items;
+    let mut inline = TableData::new_inline();
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 {
+            inline.push_comma();
+        }
+        inline.push_space(" ");
+        let value = into_inline_value(items.remove(&key).unwrap());
+        inline.insert_spaced(key, value, Some(" "), Some(" "));
+    }
+    if !inline.items.is_empty() {
+        inline.push_space(" ");
+    }
+    inline
+}
+
+/// Converts regular tables and arrays of tables into inline values.
+fn into_inline_value(value: Value) -> Value {
+    match value {
+        Value::Table(table) => {
+            if table.is_inline() {
+                Value::Table(table)
+            } else {
+                Value::Table(into_inline_table(table))
+            }
+        }
+        Value::Array(array) => {
+            if array.is_inline() {
+                return Value::Array(array);
+            }
+            let mut inline = ArrayData::new_inline();
+            for value in array.into_values() {
+                inline.push(into_inline_value(value)).expect("The elements are all tables");
+            }
+            Value::Array(inline)
+        }
+        value => value,
+    }
+}
+
+/// Converts the given inline table into a regular table with one entry per line.
+fn into_regular_table(table: TableData) -> TableData {
+    let TableData { order, mut items, .. } = table;
+    let mut regular = TableData::new_regular();
+    regular.ensure_newline_after_scope();
+    for item in order {
+        if let TableItem::Entry { key, .. } = item {
+            if let Some(value) = items.remove(&key) {
+                regular.insert(key, value);
+            }
+        }
+    }
+    regular
+}
+
+/// Merges the regular tables found in both tables, and returns the entries of the other
+/// table whose keys this table doesn't have, in the order they were written.
+fn merge_tables<'src>(table: &mut TableData<'src>,
+                      mut other: TableData<'src>)
+                      -> Vec<(Key<'src>, Value<'src>)> {
+    let keys = ordered_keys(&other);
+    let mut missing = Vec::new();
+    for key in keys {
+        let value = match other.items.remove(&key) {
+            Some(value) => value,
+            None => continue,
+        };
+        match (table.items.get_mut(&key), value) {
+            (Some(&mut Value::Table(ref mut existing)), Value::Table(other_table)) => {
+                if !existing.is_inline() && !other_table.is_inline() {
+                    for (key, value) in merge_tables(existing, other_table) {
+                        if value.is_noninline_array_of_tables() {
+                            existing.items.insert(key, value);
+                        } else {
+                            existing.insert_before_blank_lines(key, value);
+                        }
+                    }
+                }
+            }
+            (Some(_), _) => {}
+            (None, value) => missing.push((key, value)),
+        }
+    }
+    missing
+}
+
 /// A representation of a formatted TOML document.
 /// It corresponds to the top-level table, and is used to read and edit the document,
 /// while preserving its formatting.
 pub struct Document<'src> {
     tree: TableData<'src>,
     order: Vec<DocumentItem<'src>>,
+    /// Whether the document was edited since it was parsed or last marked as unmodified.
+    modified: bool,
+    /// Whether the document is written with a byte order mark.
+    bom: bool,
 }
 
 impl<'src> Document<'src> {
@@ -45,19 +470,22 @@ impl<'src> Document<'src> {
         Document {
             tree: TableData::new_regular(),
             order: Vec::new(),
+            modified: false,
+            bom: false,
         }
     }
-    
+
     /// Returns the top-level table of the document.
     pub fn root<'doc>(&'doc mut self) -> Table<'src, 'doc> {
-        Table::new(&mut self.tree, &mut self.order)
+        Table::new_root(&mut self.tree, &mut self.order, &mut self.modified)
     }
-    
+
     /// Adds an amount of whitespace to the document.
     /// Errors if the given strings contains characters other than valid
     /// TOML whitespace, that is spaces or tabs.
     pub fn push_space(&mut self, space: &'src str) -> Result<(), String> {
         if space.chars().all(|c| c == ' ' || c == '\t') {
+            self.modified = true;
             self.order.push(DocumentItem::Whitespace(space));
             Ok(())
         } else {
@@ -67,51 +495,1025 @@ impl<'src> Document<'src> {
 
     /// Adds a newline character to the document.
     pub fn push_newline(&mut self, newline: Newline) {
+        self.modified = true;
         self.order.push(DocumentItem::Newline(newline));
     }
-    
+
     /// Adds a table scope to the document.
     pub fn push_table_scope(&mut self, scope: Scope<'src>) {
-        unimplemented!();
+        self.modified = true;
+        self.order.push(DocumentItem::Table(scope));
     }
 
     /// Adds an array-of-tables scope to the document.
     pub fn push_array_scope(&mut self, scope: Scope<'src>) {
-        unimplemented!();
+        self.modified = true;
+        self.order.push(DocumentItem::ArrayScope(scope));
     }
-    
+
     /// Adds a comment to the document.
     pub fn push_comment(&mut self, text: &'src str) {
-        unimplemented!();
+        self.modified = true;
+        self.order.push(DocumentItem::Comment(text));
     }
-    
-    fn find_or_insert_table_internal<'doc>(&'doc mut self, path: &[Key<'src>]) -> Result<(&'doc mut TableData<'src>, &'doc mut Vec<DocumentItem<'src>>), InsertTableError> {
-        match *path {
-            [key] => {
-                unimplemented!();
+
+    /// Returns the array-of-tables indices of every scope in the document order.
+    fn scope_indices(&self) -> Vec<Option<ScopeIndices>> {
+        use self::DocumentItem::*;
+        let mut counts: HashMap<Vec<Key<'src>>, usize> = HashMap::new();
+        self.order
+            .iter()
+            .map(|item| {
+                let path = match *item {
+                    Table(ref scope) => scope.path(),
+                    ArrayScope(ref scope) => {
+                        let path = scope.path();
+                        *counts.entry(path.clone()).or_insert(0) += 1;
+                        // Nested arrays start over for each new element
+                        counts.retain(|other, _| {
+                            other.len() <= path.len() || !other.starts_with(path)
+                        });
+                        path
+                    }
+                    _ => return None,
+                };
+                Some((0..path.len())
+                    .map(|i| counts.get(&path[..i + 1]).map(|count| count - 1))
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// Finds or inserts a table at the given path, adding a header for it if requested,
+    /// and returns it along with the document order and modified flag. Doesn't mark the
+    /// document as modified.
+    fn find_or_insert_table_internal<'doc>(&'doc mut self,
+                                           path: &[Key<'src>],
+                                           add_scope: bool)
+        -> Result<(&'doc mut TableData<'src>,
+                   &'doc mut Vec<DocumentItem<'src>>,
+                   &'doc mut bool),
+                  InsertTableError>
+    {
+        if path.is_empty() {
+            return Err(InsertTableError::EmptyPath);
+        }
+        let has_content = !self.order.is_empty() || !self.tree.order.is_empty();
+        let order = &mut self.order;
+        let mut table = &mut self.tree;
+        for (i, key) in path.iter().enumerate() {
+            let current = table;
+            let value = current.items
+                .entry(*key)
+                .or_insert_with(|| Value::Table(TableData::new_regular()));
+            table = match *value {
+                Value::Table(ref mut table) if !table.is_inline() => {
+                    let has_scope = order.iter().any(|item| match *item {
+                        DocumentItem::Table(ref scope) => scope.path()[..] == *path,
+                        _ => false,
+                    });
+                    if add_scope && i == path.len() - 1 && !has_scope {
+                        if has_content {
+                            order.push(DocumentItem::Newline(Newline::Lf));
+                        }
+                        order.push(DocumentItem::Table(path.iter().collect()));
+                    }
+                    table
+                }
+                Value::Array(ref mut array) if !array.is_inline() => {
+                    match array.last() {
+                        Some(&mut Value::Table(ref mut table)) => table,
+                        _ => return Err(InsertTableError::PathItemNotTable(key.to_string())),
+                    }
+                }
+                _ => return Err(InsertTableError::PathItemNotTable(key.to_string())),
+            };
+        }
+        Ok((table, order, &mut self.modified))
+    }
+
+    /// Finds or inserts a table at the given path.
+    /// Errors with `EmptyPath` if the path is empty, since the top-level table is found
+    /// with `root`, and with `PathItemNotTable` if a part of the path is not a table.
+    ///
+    /// ```
+    /// use space_toml::InsertTableError;
+    ///
+    /// let mut document = space_toml::parse("[a]\nx = 1\n").unwrap();
+    /// assert!(document.find_or_insert_table(vec!["a", "b"]).is_ok());
+    /// match document.find_or_insert_table(Vec::<&str>::new()) {
+    ///     Err(InsertTableError::EmptyPath) => {}
+    ///     _ => panic!("An empty path was accepted"),
+    /// }
+    /// match document.find_or_insert_table(vec!["a", "x"]) {
+    ///     Err(InsertTableError::PathItemNotTable(ref key)) if key == "x" => {}
+    ///     _ => panic!("A path through an integer was accepted"),
+    /// }
+    /// ```
+    pub fn find_or_insert_table<'doc, I, V>(&'doc mut self, path: I)
+        -> Result<Table<'src, 'doc>, InsertTableError>
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        let slice = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let existed = table_at(&self.tree, &slice, &vec![None; slice.len()]).is_some();
+        let items = self.order.len();
+        let (table_ref, order, modified) = self.find_or_insert_table_internal(&slice, true)?;
+        if !existed || order.len() != items {
+            *modified = true;
+        }
+        Ok(Table::new(table_ref, order, modified))
+    }
+
+    /// Returns the paths of the tables that `find_or_insert_table` would create for the
+    /// given path, from the outermost one, without changing the document. Tables in
+    /// arrays of tables are found in their last element, as when inserting.
+    /// Errors like `find_or_insert_table` if the table couldn't be inserted.
+    pub fn plan_insert<'p>(&self, path: &[&'p str])
+        -> Result<Vec<Vec<Key<'p>>>, InsertTableError>
+    {
+        if path.is_empty() {
+            return Err(InsertTableError::EmptyPath);
+        }
+        let keys = path.iter().map(Key::from).collect::<Vec<_>>();
+        let mut table = &self.tree;
+        for (i, key) in keys.iter().enumerate() {
+            let current = table;
+            let value = current.iter().find(|&(other, _)| other.normalized() == key.normalized());
+            table = match value.map(|(_, value)| value) {
+                None => return Ok((i..keys.len()).map(|end| keys[..end + 1].to_vec()).collect()),
+                Some(&Value::Table(ref table)) if !table.is_inline() => table,
+                Some(&Value::Array(ref array)) if !array.is_inline() => {
+                    match array.iter().last() {
+                        Some(&Value::Table(ref table)) => table,
+                        _ => return Err(InsertTableError::PathItemNotTable(key.to_string())),
+                    }
+                }
+                Some(_) => return Err(InsertTableError::PathItemNotTable(key.to_string())),
+            };
+        }
+        Ok(Vec::new())
+    }
+
+    /// Removes the whitespace inside every table and array of tables header, so that
+    /// they are written like `[a.b]`. Returns whether any header had whitespace.
+    pub fn normalize_header_spacing(&mut self) -> bool {
+        let mut changed = false;
+        for item in &mut self.order {
+            match *item {
+                DocumentItem::Table(ref mut scope) |
+                DocumentItem::ArrayScope(ref mut scope) if scope.has_spacing() => {
+                    scope.normalize();
+                    changed = true;
+                }
+                _ => {}
             }
-            [key, _..] => {
-                unimplemented!();
+        }
+        self.modified |= changed;
+        changed
+    }
+
+    /// Sets the spacing around the equals sign of every entry to a single space, so that
+    /// they are written like `key = value`. Returns whether any spacing was changed.
+    pub fn normalize_equals_spacing(&mut self) -> bool {
+        let changed = self.tree.normalize_equals_spacing();
+        self.modified |= changed;
+        changed
+    }
+
+    /// Returns the style of the first newline in the document, or `Lf` if it has none.
+    fn newline_style(&self) -> Newline {
+        let indices = self.scope_indices();
+        let tables = self.order.iter().zip(&indices).filter_map(|(item, indices)| {
+            match (item, indices) {
+                (&DocumentItem::Table(ref scope), &Some(ref indices)) |
+                (&DocumentItem::ArrayScope(ref scope), &Some(ref indices)) => {
+                    table_at(&self.tree, scope.path(), indices)
+                }
+                _ => None,
             }
-            [] => {
-                Err(InsertTableError::EmptyPath)
+        });
+        for table in Some(&self.tree).into_iter().chain(tables) {
+            for item in &table.order {
+                if let TableItem::Newline(text) = *item {
+                    return if text == "\r\n" { Newline::CrLf } else { Newline::Lf };
+                }
             }
         }
+        Newline::Lf
     }
 
-    /// Finds or inserts a table at the given path.
-    pub fn find_or_insert_table<'doc, I, V>(&'doc mut self, path: I) 
-        -> Result<Table<'src, 'doc>, InsertTableError> 
+    /// Returns the table written at the end of the document, unless the document ends
+    /// with a comment after it.
+    fn last_table_mut(&mut self) -> Option<&mut TableData<'src>> {
+        let indices = self.scope_indices();
+        match self.order.last() {
+            Some(&DocumentItem::Table(ref scope)) |
+            Some(&DocumentItem::ArrayScope(ref scope)) => {
+                match indices.last() {
+                    Some(&Some(ref indices)) => table_at_mut(&mut self.tree, scope.path(), indices),
+                    _ => None,
+                }
+            }
+            Some(_) => None,
+            None => Some(&mut self.tree),
+        }
+    }
+
+    /// Makes the document end with a single newline, or with no newline at all, by
+    /// replacing the newlines and whitespace at its end. The added newline uses the
+    /// style of the other newlines in the document.
+    pub fn ensure_trailing_newline(&mut self, yes: bool) {
+        self.modified = true;
+        let newline = self.newline_style();
+        loop {
+            match self.order.last() {
+                Some(&DocumentItem::Whitespace(_)) |
+                Some(&DocumentItem::Newline(_)) => {}
+                _ => break,
+            }
+            self.order.pop();
+        }
+        match self.last_table_mut() {
+            Some(table) => {
+                loop {
+                    match table.order.last() {
+                        Some(&TableItem::Space(_)) |
+                        Some(&TableItem::Newline(_)) => {}
+                        _ => break,
+                    }
+                    table.order.pop();
+                }
+                if yes {
+                    table.order.push(TableItem::Newline(newline.as_str()));
+                }
+            }
+            None => {
+                if yes {
+                    self.order.push(DocumentItem::Newline(newline));
+                }
+            }
+        }
+    }
+
+    /// Returns the number of newlines after the last sub-table following the header of
+    /// the table at the given path, if it has sub-tables there.
+    fn section_trailing_newlines(&self, path: &[Key<'src>]) -> Option<usize> {
+        let indices = self.scope_indices();
+        let start = self.order.iter().position(|item| match *item {
+            DocumentItem::Table(ref scope) => *scope.path() == path,
+            _ => false,
+        })?;
+        let mut last = None;
+        for (item, indices) in self.order[start + 1..].iter().zip(&indices[start + 1..]) {
+            match (item, indices) {
+                (&DocumentItem::Table(ref scope), &Some(ref indices)) |
+                (&DocumentItem::ArrayScope(ref scope), &Some(ref indices)) => {
+                    if !scope.path().starts_with(path) {
+                        break;
+                    }
+                    last = table_at(&self.tree, scope.path(), indices);
+                }
+                _ => {}
+            }
+        }
+        last.map(TableData::trailing_newlines)
+    }
+
+    /// Replaces the table at the given path with the given regular table, creating it
+    /// if it doesn't exist. The rest of the header line of the old table and the blank
+    /// lines after it are kept, while the headers of its sub-tables are removed.
+    /// Errors if a part of the path is not a table.
+    pub fn set_table<I, V>(&mut self, path: I, table: TableData<'src>)
+        -> Result<(), InsertTableError>
         where I: IntoIterator<Item=V>, V: Into<Key<'src>>
     {
-        let slice = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
-        let (table_ref, order) = self.find_or_insert_table_internal(&slice)?;
-        Ok(Table::new(table_ref, order))
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let section_newlines = self.section_trailing_newlines(&path);
+        self.modified = true;
+        {
+            let old = self.find_or_insert_table_internal(&path, true)?.0;
+            old.replace_contents(table);
+            // Keep the spacing before the next section after the removed sub-tables
+            if let Some(count) = section_newlines {
+                if count > 0 && !old.items.is_empty() {
+                    old.set_trailing_blank_lines(count - 1);
+                }
+            }
+        }
+        // Remove the headers of tables that no longer exist, with their separating line
+        let indices = self.scope_indices();
+        let tree = &self.tree;
+        remove_headers(&mut self.order, |i, scope| match indices[i] {
+            Some(ref indices) => table_at(tree, scope.path(), indices).is_none(),
+            None => false,
+        });
+        Ok(())
+    }
+
+    /// Reformats the array of tables at the given path, so that its elements are
+    /// separated by a single blank line, and their headers contain no whitespace.
+    /// Returns whether any array of tables was found at the path.
+    pub fn reformat_array_of_tables<I, V>(&mut self, path: I) -> bool
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        self.reformat_array_of_tables_spaced(path, 1)
+    }
+
+    /// Reformats the array of tables at the given path, so that its elements are
+    /// separated by the given number of blank lines, and their headers contain no
+    /// whitespace.
+    /// Returns whether any array of tables was found at the path.
+    pub fn reformat_array_of_tables_spaced<I, V>(&mut self, path: I, blank_lines: usize) -> bool
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let indices = self.scope_indices();
+        let mut found = false;
+        let mut previous_scope: Option<usize> = None;
+        let mut whitespace = Vec::new();
+        for i in 0..self.order.len() {
+            let previous = previous_scope;
+            if indices[i].is_some() {
+                previous_scope = Some(i);
+            }
+            let is_element = match self.order[i] {
+                DocumentItem::ArrayScope(ref mut scope) if *scope.path() == path => {
+                    scope.normalize();
+                    true
+                }
+                _ => false,
+            };
+            if is_element {
+                found = true;
+                let is_first = indices[i].as_ref().and_then(|idx| idx[path.len() - 1]) == Some(0);
+                let prev = match previous {
+                    Some(prev) if !is_first => prev,
+                    _ => continue,
+                };
+                // Remove the document-level whitespace between the elements
+                for j in prev + 1..i {
+                    match self.order[j] {
+                        DocumentItem::Whitespace(_) |
+                        DocumentItem::Newline(_) => whitespace.push(j),
+                        _ => {}
+                    }
+                }
+                let prev_path = match self.order[prev] {
+                    DocumentItem::Table(ref scope) |
+                    DocumentItem::ArrayScope(ref scope) => scope.path().clone(),
+                    _ => continue,
+                };
+                if let Some(ref prev_indices) = indices[prev] {
+                    if let Some(table) = table_at_mut(&mut self.tree, &prev_path, prev_indices) {
+                        table.set_trailing_blank_lines(blank_lines);
+                    }
+                }
+            }
+        }
+        for i in whitespace.into_iter().rev() {
+            self.order.remove(i);
+        }
+        self.modified |= found;
+        found
+    }
+
+    /// Returns the table of the last table or array of tables header before the given
+    /// index of the document order, or the top-level table if there is none.
+    fn last_table_before(&mut self,
+                         index: usize,
+                         indices: &[Option<ScopeIndices>])
+                         -> Option<&mut TableData<'src>> {
+        let scope = self.order[..index].iter().zip(indices).rev().filter_map(|pair| {
+            match pair {
+                (&DocumentItem::Table(ref scope), &Some(ref indices)) |
+                (&DocumentItem::ArrayScope(ref scope), &Some(ref indices)) => {
+                    Some((scope.path().clone(), indices.clone()))
+                }
+                _ => None,
+            }
+        }).next();
+        match scope {
+            Some((path, indices)) => table_at_mut(&mut self.tree, &path, &indices),
+            None => Some(&mut self.tree),
+        }
+    }
+
+    /// Appends the given table to the array of tables at the given path, creating the
+    /// array if it doesn't exist. The header of the new element is written after the
+    /// last element and the headers of its sub-tables, separated from it by the same
+    /// number of blank lines as the last two elements, or a single blank line if there
+    /// are fewer elements.
+    /// Errors if a part of the path is not a table, or the value at the path isn't an
+    /// array of tables.
+    pub fn add_array_entry<I, V>(&mut self, path: I, table: TableData<'src>)
+        -> Result<(), InsertTableError>
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let (key, parent_path) = match path.split_last() {
+            Some(parts) => parts,
+            None => return Err(InsertTableError::EmptyPath),
+        };
+        {
+            let parent = if parent_path.is_empty() {
+                &mut self.tree
+            } else {
+                self.find_or_insert_table_internal(parent_path, false)?.0
+            };
+            match *parent.items.entry(*key).or_insert_with(|| ArrayData::new_of_tables().into()) {
+                Value::Array(ref array) if !array.is_inline() => {}
+                _ => return Err(InsertTableError::PathItemNotTable(key.to_string())),
+            }
+        }
+        let indices = self.scope_indices();
+        let elements = self.order
+            .iter()
+            .enumerate()
+            .filter(|&(_, item)| match *item {
+                DocumentItem::ArrayScope(ref scope) => *scope.path() == path,
+                _ => false,
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let blank_lines = match elements.len() {
+            0 | 1 => 1,
+            count => {
+                self.last_table_before(elements[count - 1], &indices)
+                    .map_or(1, |table| table.trailing_newlines().saturating_sub(1))
+            }
+        };
+        let position = match elements.last() {
+            Some(&last) => {
+                let sub_tables = self.order[last + 1..]
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, item)| match *item {
+                        DocumentItem::Table(ref scope) |
+                        DocumentItem::ArrayScope(ref scope) => Some((i, scope.path())),
+                        _ => None,
+                    })
+                    .take_while(|&(_, other)| other.starts_with(&path))
+                    .last();
+                last + 1 + sub_tables.map_or(0, |(i, _)| i + 1)
+            }
+            None => self.order.len(),
+        };
+        let mut table = if table.is_inline() { into_regular_table(table) } else { table };
+        table.ensure_newline_after_scope();
+        if let Some(previous) = self.last_table_before(position, &indices) {
+            if !previous.order.is_empty() {
+                let trailing_newlines = previous.trailing_newlines();
+                previous.set_trailing_blank_lines(blank_lines);
+                if trailing_newlines > 0 && table.has_written_entries() {
+                    table.set_trailing_blank_lines(trailing_newlines - 1);
+                }
+            }
+        }
+        {
+            let parent = if parent_path.is_empty() {
+                &mut self.tree
+            } else {
+                self.find_or_insert_table_internal(parent_path, false)?.0
+            };
+            if let Some(&mut Value::Array(ref mut array)) = parent.items.get_mut(key) {
+                array.push_value(Value::Table(table)).expect("The array holds tables");
+            }
+        }
+        self.order.insert(position, DocumentItem::ArrayScope(path.iter().collect()));
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Converts the array of tables at the given path into an entry holding an inline
+    /// array of inline tables, written with the other entries of the table containing
+    /// it. The headers of the array elements and of their sub-tables are removed, along
+    /// with any comments inside the elements.
+    /// Returns whether any array of tables was found at the path.
+    pub fn inline_array_of_tables<I, V>(&mut self, path: I) -> bool
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        {
+            let (key, parent_path) = match path.split_last() {
+                Some(parts) => parts,
+                None => return false,
+            };
+            let indices = vec![None; parent_path.len()];
+            let parent = match table_at_mut(&mut self.tree, parent_path, &indices) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            match parent.items.get(key) {
+                Some(&Value::Array(ref array)) if !array.is_inline() => {}
+                _ => return false,
+            }
+            let array = parent.items.remove(key).unwrap();
+            parent.insert(*key, into_inline_value(array));
+        }
+        self.modified = true;
+        // Remove the headers of the elements, with their separating line
+        remove_headers(&mut self.order, |_, scope| scope.path().starts_with(&path));
+        true
+    }
+
+    /// Converts the entry at the given path holding an inline array of inline tables
+    /// into an array of tables, whose elements are written under headers of their own
+    /// at the end of the document, separated by blank lines.
+    /// Returns whether a non-empty array of only inline tables was found at the path.
+    pub fn expand_inline_array<I, V>(&mut self, path: I) -> bool
+        where I: IntoIterator<Item=V>, V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let count = {
+            let (key, parent_path) = match path.split_last() {
+                Some(parts) => parts,
+                None => return false,
+            };
+            let indices = vec![None; parent_path.len()];
+            let parent = match table_at_mut(&mut self.tree, parent_path, &indices) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            let is_tables = match parent.items.get(key) {
+                Some(&Value::Array(ref array)) => {
+                    array.is_inline() && !parent.is_inline() && !array.is_empty() &&
+                    array.iter().all(Value::is_table)
+                }
+                _ => false,
+            };
+            if !is_tables {
+                return false;
+            }
+            let values = match parent.remove(key) {
+                Some(Value::Array(array)) => array.into_values(),
+                _ => return false,
+            };
+            let mut array = ArrayData::new_of_tables();
+            for value in values {
+                if let Value::Table(table) = value {
+                    let table = Value::Table(into_regular_table(table));
+                    array.push_value(table).expect("The elements are all tables");
+                }
+            }
+            let count = array.len();
+            parent.items.insert(*key, Value::Array(array));
+            count
+        };
+        self.modified = true;
+        let newline = self.newline_style();
+        let has_content = !self.order.is_empty() || !self.tree.order.is_empty();
+        // Leave a single blank line after the preceding table
+        if let Some(table) = self.last_table_mut() {
+            if has_content {
+                table.set_trailing_blank_lines(0);
+            }
+        }
+        for _ in 0..count {
+            if !self.order.is_empty() || !self.tree.order.is_empty() {
+                self.order.push(DocumentItem::Newline(newline));
+            }
+            self.order.push(DocumentItem::ArrayScope(path.iter().collect()));
+        }
+        true
+    }
+
+    /// Returns the key paths of every value in the document that isn't a table, in
+    /// sorted order. The values in arrays of tables are found through the index of
+    /// their table, like `servers[0].ip`, while inline arrays are single values.
+    pub fn leaf_paths(&self) -> Vec<Vec<PathPart>> {
+        self.leaves().into_iter().map(|(path, _)| path).collect()
+    }
+
+    /// Returns the leaf values of the document by their key paths.
+    fn leaves(&self) -> BTreeMap<Vec<PathPart>, &Value<'src>> {
+        let mut leaves = BTreeMap::new();
+        collect_leaves(&self.tree, &mut Vec::new(), &mut leaves);
+        leaves
+    }
+
+    /// Returns the paths and the text of every datetime value in the document, in sorted
+    /// order of their paths. The values in arrays are found through their index, like
+    /// `dates[1]`.
+    pub fn datetimes<'a>(&'a self) -> impl Iterator<Item = (Vec<PathPart>, &'a str)> {
+        self.values_of_type(TomlType::Datetime)
+            .filter_map(|(path, value)| value.datetime().map(|text| (path, text)))
+    }
+
+    /// Returns the paths of every value of the given type in the document, along with the
+    /// values, in sorted order of their paths. Tables include those under headers, and
+    /// arrays include arrays of tables, while the values in arrays are found through
+    /// their index, like `ports[1]`.
+    pub fn values_of_type<'a>(&'a self, ty: TomlType)
+        -> impl Iterator<Item = (Vec<PathPart>, &'a Value<'src>)>
+    {
+        let mut values = BTreeMap::new();
+        for (key, value) in self.tree.iter() {
+            let mut path = vec![PathPart::Key(key.to_string())];
+            collect_values(value, ty, &mut path, &mut values);
+        }
+        values.into_iter()
+    }
+
+    /// Calls the given function with the path and a mutable reference of the root table,
+    /// and then of every table declared by a header, in the order of the document.
+    /// Each element of an array of tables is visited with the path of the array.
+    pub fn for_each_table_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&[Key<'src>], &mut TableData<'src>)
+    {
+        self.modified = true;
+        f(&[], &mut self.tree);
+        let mut visited = Vec::new();
+        for (item, indices) in self.order.iter().zip(self.scope_indices()) {
+            match (item, indices) {
+                (&DocumentItem::Table(ref scope), Some(indices)) |
+                (&DocumentItem::ArrayScope(ref scope), Some(indices)) => {
+                    let section = (scope.path().clone(), indices);
+                    if !visited.contains(&section) {
+                        visited.push(section);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (path, indices) in visited {
+            if let Some(table) = table_at_mut(&mut self.tree, &path, &indices) {
+                f(&path, table);
+            }
+        }
+    }
+
+    /// Returns the most common indentation of the indented entries of the document, like
+    /// two spaces or a tab, preferring the shortest one on ties. Returns `None` if no
+    /// entry is indented.
+    pub fn detect_indent(&self) -> Option<&'src str> {
+        let mut tables = vec![&self.tree];
+        for (item, indices) in self.order.iter().zip(self.scope_indices()) {
+            match (item, indices) {
+                (&DocumentItem::Table(ref scope), Some(indices)) |
+                (&DocumentItem::ArrayScope(ref scope), Some(indices)) => {
+                    tables.extend(table_at(&self.tree, scope.path(), &indices));
+                }
+                _ => {}
+            }
+        }
+        let mut counts: HashMap<&'src str, usize> = HashMap::new();
+        for table in tables {
+            let order = &table.order;
+            for (i, pair) in order.windows(2).enumerate() {
+                if let (&TableItem::Space(text), &TableItem::Entry { .. }) = (&pair[0], &pair[1]) {
+                    let line_start = match order[..i].last() {
+                        None | Some(&TableItem::Newline(_)) => true,
+                        _ => false,
+                    };
+                    if line_start && !text.is_empty() {
+                        *counts.entry(text).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        counts.into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.len().cmp(&a.0.len())).then(b.0.cmp(a.0)))
+            .map(|(indent, _)| indent)
+    }
+
+    /// Returns the comments of the document in order, along with the entry or table that
+    /// each comment is attached to. Comments inside arrays are not included.
+    pub fn trivia(&self) -> Vec<Trivia<'src>> {
+        let mut events = Vec::new();
+        push_table_events(&self.tree, &[], &mut events);
+        for (item, indices) in self.order.iter().zip(self.scope_indices()) {
+            match (item, indices) {
+                (&DocumentItem::Whitespace(_), _) => events.push(Event::Space),
+                (&DocumentItem::Newline(_), _) => events.push(Event::Newline),
+                (&DocumentItem::Comment(text), _) => events.push(Event::Comment(text)),
+                (&DocumentItem::Table(ref scope), Some(indices)) |
+                (&DocumentItem::ArrayScope(ref scope), Some(indices)) => {
+                    let path = indexed_path(scope.path(), &indices);
+                    events.push(Event::Header(path.clone()));
+                    if let Some(table) = table_at(&self.tree, scope.path(), &indices) {
+                        push_table_events(table, &path, &mut events);
+                    }
+                }
+                _ => {}
+            }
+        }
+        trivia::classify(&events)
+    }
+
+    /// Returns the comment on the line directly above the header of the table at the
+    /// given path, without the leading `#`. For arrays of tables, the comment above the
+    /// first header that has one is returned.
+    pub fn table_comment(&self, path: &[&str]) -> Option<&'src str> {
+        let key_path = path.iter().map(|key| PathPart::Key(key.to_string())).collect::<Vec<_>>();
+        let trivia = self.trivia();
+        let mut leading = trivia.iter().enumerate().filter(|&(_, trivia)| {
+            let keys = trivia.path.iter().filter(|part| match **part {
+                PathPart::Index(_) => false,
+                PathPart::Key(_) => true,
+            });
+            trivia.attachment == Attachment::Leading && keys.eq(key_path.iter())
+        });
+        // Of several comment lines above a header, the last one is directly above it
+        let (mut last, mut comment) = leading.next()?;
+        for (index, trivia) in leading {
+            if index == last + 1 && trivia.path == comment.path {
+                last = index;
+                comment = trivia;
+            } else {
+                break;
+            }
+        }
+        Some(comment.text)
+    }
+
+    /// Rewrites every string value of the document in the given style where its content
+    /// permits it. Strings that would need escapes are written as basic strings.
+    pub fn convert_string_style(&mut self, style: StringStyle) {
+        self.modified = true;
+        for (_, value) in self.tree.iter_mut() {
+            value.convert_string_style(style);
+        }
+    }
+
+    /// Returns the byte range of the key of the entry at the given path in the source text
+    /// of the document, descending through tables. Returns `None` if there is no entry at
+    /// the path, or it was inserted after parsing.
+    pub fn key_span(&self, path: &[&str]) -> Option<Range<usize>> {
+        let (last, rest) = path.split_last()?;
+        let table = if rest.is_empty() {
+            &self.tree
+        } else {
+            self.tree.get_path(rest.iter().cloned())?.table()?
+        };
+        table.key_span(*last)
+    }
+
+    /// Returns the plain data of the document, without its formatting.
+    pub fn to_map(&self) -> BTreeMap<String, TomlData> {
+        self.tree.to_map()
+    }
+
+    /// Returns the values of the document that don't match the given schema: missing
+    /// required values and values of the wrong type.
+    pub fn validate(&self, schema: &Schema) -> Vec<SchemaError> {
+        schema.validate(&self.tree)
+    }
+
+    /// Returns the values that differ between this and the other document, ignoring
+    /// formatting. Removed and modified values are listed before added values.
+    pub fn diff<'a>(&'a self, other: &'a Document<'src>) -> Vec<Change<'a, 'src>> {
+        let old = self.leaves();
+        let new = other.leaves();
+        let mut changes = Vec::new();
+        for (path, old_value) in &old {
+            match new.get(path) {
+                None => changes.push(Change::Removed(path.clone())),
+                Some(new_value) if old_value != new_value => {
+                    changes.push(Change::Modified(path.clone(), old_value, new_value));
+                }
+                Some(_) => {}
+            }
+        }
+        for path in new.keys() {
+            if !old.contains_key(path) {
+                changes.push(Change::Added(path.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Merges the other document into this one, keeping the values of this document.
+    /// The entries of the other document whose keys this one doesn't have are added, and
+    /// the tables found in both are merged, while the tables that only the other
+    /// document has are written under new headers at the end.
+    pub fn merge(&mut self, other: Document<'src>) {
+        self.modified = true;
+        for (key, value) in merge_tables(&mut self.tree, other.tree) {
+            if value.is_noninline_array_of_tables() {
+                self.tree.items.insert(key, value);
+            } else if self.tree.order.is_empty() {
+                self.root().insert_smart(key, value);
+            } else {
+                self.tree.insert_before_blank_lines(key, value);
+            }
+        }
+    }
+
+    /// Resolves the includes of this document: the names found at the given key of the
+    /// top-level table are loaded with the given function, and the parsed documents are
+    /// merged into this one in order, after their own includes are resolved. The include
+    /// entry is removed, and can be a name or an array of names.
+    /// Values of this document take precedence over included ones, and earlier includes
+    /// over later ones. Since documents borrow their text, the loaded texts must outlive
+    /// this document.
+    pub fn resolve_includes<F>(&mut self, key: &'src str, mut load: F)
+        -> Result<(), IncludeError<'src>>
+        where F: FnMut(&str) -> Option<&'src str>
+    {
+        self.resolve_includes_from(key, &mut load, &mut Vec::new())
+    }
+
+    /// Resolves the includes of this document, which is included through the given
+    /// stack of names.
+    fn resolve_includes_from<F>(&mut self,
+                                key: &'src str,
+                                load: &mut F,
+                                stack: &mut Vec<String>)
+                                -> Result<(), IncludeError<'src>>
+        where F: FnMut(&str) -> Option<&'src str>
+    {
+        let names = match self.tree.get(key) {
+            None => return Ok(()),
+            Some(&Value::Array(ref array)) => {
+                array.iter().map(|name| name.string().map(Cow::into_owned)).collect()
+            }
+            Some(name) => name.string().map(|name| vec![name.into_owned()]),
+        };
+        let names: Vec<String> = names.ok_or(IncludeError::InvalidValue)?;
+        self.tree.remove(&Key::from(key));
+        self.modified = true;
+        for name in names {
+            if stack.contains(&name) {
+                return Err(IncludeError::Cycle(name));
+            }
+            let text = load(&name).ok_or_else(|| IncludeError::NotFound(name.clone()))?;
+            let mut included = parse::parse(text).map_err(IncludeError::Parse)?;
+            stack.push(name);
+            included.resolve_includes_from(key, load, stack)?;
+            stack.pop();
+            self.merge(included);
+        }
+        Ok(())
+    }
+
+    /// Returns whether this document was edited since it was parsed, or since
+    /// `reset_modified` was last called. New documents start out unmodified.
+    /// Every edit counts, even if it is undone later or doesn't change the text, and
+    /// so does getting mutable access to a value of the document.
+    pub fn is_modified(&self) -> bool {
+        self.modified
     }
-    
+
+    /// Marks this document as unmodified.
+    pub fn reset_modified(&mut self) {
+        self.modified = false;
+    }
+
+    /// Returns whether this document is written with a byte order mark (`U+FEFF`) at
+    /// the start, which is the case for parsed documents whose text began with one.
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Sets whether this document is written with a byte order mark at the start.
+    pub fn set_bom(&mut self, bom: bool) {
+        self.modified |= self.bom != bom;
+        self.bom = bom;
+    }
+
     /// Writes this document to a string.
-    pub fn write(&self, string: &mut String) {
-        unimplemented!();
+    pub fn write(&self, out: &mut String) {
+        self.write_values_with(out, |_, _, _| false);
+    }
+
+    /// Writes this document to a string, letting the given function replace the written
+    /// text of values without changing the document, for instance to mask secrets.
+    /// The function is called with the path and value of every entry, and returns the
+    /// text to write instead of the value, or `None` to write the value as it is.
+    /// Inline tables that aren't replaced have their entries passed to it as well.
+    pub fn write_with<'a, F>(&self, out: &mut String, mut f: F)
+        where F: FnMut(&[PathPart], &Value<'src>) -> Option<Cow<'a, str>>
+    {
+        self.write_values_with(out, |path, value, out| match f(path, value) {
+            Some(text) => {
+                out.push_str(&text);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Returns the smallest change to the written document that sets the value at the
+    /// given path: the byte range of the old value in the output of `write`, and the text
+    /// of the new value to replace it with. For a document that hasn't been edited, the
+    /// range is that of the value in the source text.
+    /// Returns `None` if no entry is written at the path.
+    pub fn patch_value<V: Into<Value<'src>>>(&self,
+                                             path: &[PathPart],
+                                             value: V)
+                                             -> Option<(Range<usize>, String)> {
+        let mut range = None;
+        let mut out = String::new();
+        self.write_values_with(&mut out, |current, value, out| {
+            if current == path {
+                let start = out.len();
+                value.write(out);
+                range = Some(start..out.len());
+                true
+            } else {
+                false
+            }
+        });
+        let mut text = String::new();
+        value.into().write(&mut text);
+        range.map(|range| (range, text))
+    }
+
+    /// Replaces the values at the given key paths, and returns the written document.
+    /// The values keep the formatting of their entries, so the text outside of the
+    /// replaced values is the same as before. Paths without a value written as an entry
+    /// are ignored, as are regular tables given as values.
+    pub fn apply_patches(&mut self, patches: Vec<(Vec<String>, Value<'src>)>) -> String {
+        for (path, value) in patches {
+            if let Some(old) = value_at_mut(&mut self.tree, &path) {
+                let under_header = |value: &Value| match *value {
+                    Value::Table(ref table) => !table.is_inline(),
+                    Value::Array(ref array) => !array.is_inline(),
+                    _ => false,
+                };
+                if !under_header(old) && !under_header(&value) {
+                    *old = value;
+                    self.modified = true;
+                }
+            }
+        }
+        let mut out = String::new();
+        self.write(&mut out);
+        out
+    }
+
+    /// Parses the given text as a value, and replaces the value of the entry at the given
+    /// key path with it, keeping the formatting around it. The keys are compared by
+    /// their normalized text.
+    /// Returns whether a value written as an entry was found at the path, or the error
+    /// found when parsing the text, with positions relative to it.
+    pub fn set_value_from_str(&mut self,
+                              path: &[&str],
+                              raw: &'src str)
+                              -> Result<bool, Error<'src>> {
+        let value = parse::parse_value(raw)?;
+        let path = path.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        if let Some(old) = value_at_mut(&mut self.tree, &path) {
+            if !old.is_noninline_table() && !old.is_noninline_array_of_tables() {
+                *old = value;
+                self.modified = true;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Writes this document to a string, letting the given function write each entry value
+    /// in its place. The function returns whether it wrote the value.
+    fn write_values_with<F>(&self, out: &mut String, mut f: F)
+        where F: FnMut(&[PathPart], &Value<'src>, &mut String) -> bool
+    {
+        use self::DocumentItem::*;
+        if self.bom {
+            out.push('\u{FEFF}');
+        }
+        tabledata::write_with(&self.tree, &mut Vec::new(), &mut f, out);
+        let indices = self.scope_indices();
+        for (item, indices) in self.order.iter().zip(indices) {
+            match *item {
+                Whitespace(text) => out.push_str(text),
+                Newline(newline) => out.push_str(newline.as_str()),
+                Comment(text) => {
+                    out.push('#');
+                    out.push_str(text);
+                }
+                Table(ref scope) | ArrayScope(ref scope) => {
+                    let is_array = if let ArrayScope(_) = *item { true } else { false };
+                    scope.write(out, is_array);
+                    let indices = indices.unwrap();
+                    if let Some(table) = table_at(&self.tree, scope.path(), &indices) {
+                        let mut path = indexed_path(scope.path(), &indices);
+                        tabledata::write_with(table, &mut path, &mut f, out);
+                    }
+                }
+            }
+        }
+        let mut scoped = HashSet::new();
+        let mut array_scopes = HashMap::new();
+        for item in &self.order {
+            match *item {
+                Table(ref scope) => {
+                    scoped.insert(scope.path().clone());
+                }
+                ArrayScope(ref scope) => {
+                    *array_scopes.entry(scope.path().clone()).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+        write_unscoped_tables(&self.tree, &mut Vec::new(), &scoped, &array_scopes, &mut f, out);
+    }
+}
+
+impl<'src> Default for Document<'src> {
+    /// Creates an empty document.
+    fn default() -> Document<'src> {
+        Document::new()
     }
 }
 
@@ -119,31 +1521,50 @@ impl<'src> Document<'src> {
 pub trait DocumentPrivate<'src> {
     /// Pushes a space to the document order without validating.
     fn push_space_unchecked(&mut self, space: &'src str);
-    
+
     /// Pushes a table scope to the document order without validating.
     fn push_table_scope_unchecked(&mut self, scope: Scope<'src>);
-    
+
     /// Pushes an array-of-tables scope to the document order without validating.
     fn push_array_scope_unchecked(&mut self, scope: Scope<'src>);
-    
+
     /// Pushes a comment to the document order without validating.
     fn push_comment_unchecked(&mut self, text: &'src str);
+
+    /// Finds or inserts a table at the given path, without adding a scope for it.
+    fn find_or_insert_table_unscoped<'doc>(&'doc mut self,
+                                           path: &[Key<'src>])
+                                           -> Result<Table<'src, 'doc>, InsertTableError>;
+
+    /// Marks the document as read with a byte order mark, without marking it as modified.
+    fn read_bom(&mut self);
 }
 
 impl<'src> DocumentPrivate<'src> for Document<'src> {
     fn push_space_unchecked(&mut self, space: &'src str) {
         self.order.push(DocumentItem::Whitespace(space));
     }
-    
+
     fn push_table_scope_unchecked(&mut self, scope: Scope<'src>) {
         self.order.push(DocumentItem::Table(scope));
     }
-    
+
     fn push_array_scope_unchecked(&mut self, scope: Scope<'src>) {
         self.order.push(DocumentItem::ArrayScope(scope));
     }
-    
+
     fn push_comment_unchecked(&mut self, text: &'src str) {
         self.order.push(DocumentItem::Comment(text));
     }
+
+    fn find_or_insert_table_unscoped<'doc>(&'doc mut self,
+                                           path: &[Key<'src>])
+                                           -> Result<Table<'src, 'doc>, InsertTableError> {
+        let (table_ref, order, modified) = self.find_or_insert_table_internal(path, false)?;
+        Ok(Table::new(table_ref, order, modified))
+    }
+
+    fn read_bom(&mut self) {
+        self.bom = true;
+    }
 }
@@ -0,0 +1,77 @@
+use lexer;
+use parse;
+use std::error;
+use std::fmt;
+use std::ops::Range;
+
+/// An error found when lexing or parsing a TOML document.
+/// Both lexer and parser errors convert into it, so the errors of every step can be
+/// handled as one type.
+#[derive(Debug, Clone)]
+pub enum TomlError<'a> {
+    /// The text could not be split into tokens.
+    Lex(lexer::Error<'a>),
+    /// The tokens of the text don't form a valid document.
+    Parse(parse::Error<'a>),
+}
+
+impl<'a> TomlError<'a> {
+    /// Returns the text that was being lexed or parsed.
+    pub fn text(&self) -> &'a str {
+        match *self {
+            TomlError::Lex(ref err) => err.text,
+            TomlError::Parse(ref err) => err.text,
+        }
+    }
+
+    /// Returns the byte range of the text that the error points at, or `None` for
+    /// errors that don't point at the text.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match *self {
+            TomlError::Lex(ref err) => Some(err.span()),
+            TomlError::Parse(ref err) => err.span(),
+        }
+    }
+
+    /// Returns the message of the error without the excerpt of the text.
+    pub fn message(&self) -> String {
+        match *self {
+            TomlError::Lex(ref err) => err.message(),
+            TomlError::Parse(ref err) => err.message(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for TomlError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TomlError::Lex(ref err) => err.fmt(f),
+            TomlError::Parse(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl<'a> error::Error for TomlError<'a> {
+    fn description(&self) -> &str {
+        match *self {
+            TomlError::Lex(_) => "A lexer error",
+            TomlError::Parse(_) => "An error found while parsing TOML",
+        }
+    }
+}
+
+impl<'a> From<lexer::Error<'a>> for TomlError<'a> {
+    fn from(err: lexer::Error<'a>) -> TomlError<'a> {
+        TomlError::Lex(err)
+    }
+}
+
+impl<'a> From<parse::Error<'a>> for TomlError<'a> {
+    /// Lexer errors found while parsing become `TomlError::Lex`.
+    fn from(err: parse::Error<'a>) -> TomlError<'a> {
+        match err.kind {
+            parse::ErrorKind::Lex(err) => TomlError::Lex(err),
+            _ => TomlError::Parse(err),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::iter::{Iterator, Peekable};
 use std::str::CharIndices;
 use debug;
+use datetime;
 use std::result;
 use std::error;
 use std::fmt;
 use std::string;
 use std::char;
+use std::ops::Range;
 
 type CharStream<'a> = Peekable<CharIndices<'a>>;
 
@@ -21,6 +23,14 @@ pub fn tokens(text: &str) -> Tokens {
     Tokens::new(text)
 }
 
+/// Returns an iterator over the TOML tokens in the given text, which starts with a
+/// value, like the text after the equals sign of an entry.
+pub fn value_tokens(text: &str) -> Tokens {
+    let mut tokens = Tokens::new(text);
+    tokens.scope = LexerScope::Value;
+    tokens
+}
+
 /// An iterator over the TOML tokens in a unicode text.
 #[derive(Debug)]
 pub struct Tokens<'a> {
@@ -154,6 +164,13 @@ impl<'a> Tokens<'a> {
         let ch = if open { '[' } else { ']' };
         if let LexerScope::Key = self.scope {
             if self.peek_is(ch) {
+                // Arrays of tables are tracked like a single scope
+                if open {
+                    self.scope_stack.push('[');
+                } else if self.scope_stack.pop().is_none() {
+                    self.finished = true;
+                    return self.err(UnmatchedClosingBrace { pos: start });
+                }
                 self.chars.next(); // eat it
                 self.start += 1;
                 if open {
@@ -331,25 +348,48 @@ impl<'a> Tokens<'a> {
         }
     }
 
-    // TODO: Don't do this as brokenly
+    /// Reads a datetime, date or time, and checks that it is valid.
     fn read_datetime(&mut self) -> Result<'a> {
         use self::Token::*;
+        use self::ErrorKind::*;
         let start = self.start;
+        let mut end = self.text.len();
         while let Some(&(i, ch)) = self.chars.peek() {
             match ch {
-                '0'...'9' | '-' | 'T' | ':' | 't' | 'Z' | '.' => {
+                '0'..='9' | '-' | '+' | ':' | '.' | 'T' | 't' | 'Z' | 'z' => {
+                    self.chars.next();
+                }
+                // The date and time may be separated by a space instead of a `T`
+                ' ' if i - start == 10 && self.text[i + 1..].starts_with(|c: char| {
+                    c.is_digit(10)
+                }) => {
                     self.chars.next();
                 }
                 _ => {
-                    let part = &self.text[self.start..i];
-                    self.start = i;
-                    return Ok((start, DateTime(part)));
+                    end = i;
+                    break;
                 }
             }
         }
-        let part = &self.text[self.start..];
-        self.start = self.text.len();
-        Ok((start, DateTime(part)))
+        let part = &self.text[start..end];
+        let datetime = match datetime::parse_datetime(part) {
+            Ok(datetime) => datetime,
+            Err(offset) => {
+                self.finished = true;
+                return self.err(InvalidDateTime {
+                    start: start,
+                    pos: start + offset,
+                });
+            }
+        };
+        self.start = end;
+        let token = match (datetime.date, datetime.time, datetime.offset) {
+            (Some(_), Some(_), Some(_)) => OffsetDateTime(part),
+            (Some(_), Some(_), None) => LocalDateTime(part),
+            (Some(_), None, _) => LocalDate(part),
+            _ => LocalTime(part),
+        };
+        Ok((start, token))
     }
 
     /// Reads an integer.
@@ -363,7 +403,7 @@ impl<'a> Tokens<'a> {
                     was_number = true;
                     self.chars.next();
                 }
-                '-' if datetime_possible => {
+                '-' | ':' if datetime_possible => {
                     return self.read_datetime();
                 }
                 '.' => {
@@ -389,12 +429,58 @@ impl<'a> Tokens<'a> {
                         pos: i,
                     });
                 }
-                ',' | ' ' | '\t' | '\n' | ']' | '#' => {
+                ',' | ' ' | '\t' | '\r' | '\n' | ']' | '}' | '#' => {
+                    let part = &self.text[self.start..i];
+                    self.start = i;
+                    return Ok((start, Int(part)));
+                }
+                _ => {
+                    return self.err(InvalidIntCharacter {
+                        start: self.start,
+                        pos: i,
+                    });
+                }
+            }
+        }
+        let part = &self.text[self.start..];
+        Ok((start, Int(part)))
+    }
+
+    /// Reads a hexadecimal, octal or binary integer, starting at its radix prefix.
+    fn read_radix_int(&mut self) -> Result<'a> {
+        use self::Token::*;
+        use self::ErrorKind::*;
+        let start = self.start;
+        let radix = match self.chars.next() {
+            Some((_, 'x')) => 16,
+            Some((_, 'o')) => 8,
+            _ => 2,
+        };
+        let mut was_number = false;
+        while let Some(&(i, ch)) = self.chars.peek() {
+            match ch {
+                _ if ch.is_digit(radix) => {
+                    was_number = true;
+                    self.chars.next();
+                }
+                '_' if was_number => {
+                    self.chars.next();
+                    was_number = false;
+                }
+                '_' => {
+                    self.finished = true;
+                    return self.err(UnderscoreNotAfterNumber {
+                        start: self.start,
+                        pos: i,
+                    });
+                }
+                ',' | ' ' | '\t' | '\r' | '\n' | ']' | '}' | '#' if was_number => {
                     let part = &self.text[self.start..i];
                     self.start = i;
                     return Ok((start, Int(part)));
                 }
                 _ => {
+                    self.finished = true;
                     return self.err(InvalidIntCharacter {
                         start: self.start,
                         pos: i,
@@ -402,6 +488,13 @@ impl<'a> Tokens<'a> {
                 }
             }
         }
+        if !was_number {
+            self.finished = true;
+            return self.err(InvalidIntCharacter {
+                start: self.start,
+                pos: self.text.len(),
+            });
+        }
         let part = &self.text[self.start..];
         Ok((start, Int(part)))
     }
@@ -442,7 +535,7 @@ impl<'a> Tokens<'a> {
                         pos: i,
                     });
                 }
-                ',' | ' ' | '\t' | '\n' | ']' | '#' => {
+                ',' | ' ' | '\t' | '\r' | '\n' | ']' | '}' | '#' => {
                     let part = &self.text[self.start..i];
                     self.start = i;
                     return Ok((start, Float(part)));
@@ -459,6 +552,22 @@ impl<'a> Tokens<'a> {
         Ok((start, Float(part)))
     }
 
+    /// Reads an infinite or not-a-number float, whose `inf` or `nan` starts at the given
+    /// index after an optional sign.
+    fn read_special_float(&mut self, pos: usize) -> Result<'a> {
+        use self::Token::*;
+        let start = self.start;
+        let end = pos + 3;
+        while let Some(&(i, _)) = self.chars.peek() {
+            if i >= end {
+                break;
+            }
+            self.chars.next();
+        }
+        self.start = end;
+        Ok((start, Float(&self.text[start..end])))
+    }
+
     /// Reads a value. (right hand of an assignment or part of an array).
     fn read_value(&mut self, i: usize, ch: char) -> Result<'a> {
         use self::Token::*;
@@ -494,7 +603,16 @@ impl<'a> Tokens<'a> {
                     pos: i,
                 })
             }
+            '-' | '+' if self.next_is(i + 1, "inf") || self.next_is(i + 1, "nan") => {
+                self.read_special_float(i + 1)
+            }
+            'i' | 'n' if self.next_is(i, "inf") || self.next_is(i, "nan") => {
+                self.read_special_float(i)
+            }
             '-' | '+' => self.read_int(false, false),
+            '0' if self.peek_is('x') || self.peek_is('o') || self.peek_is('b') => {
+                self.read_radix_int()
+            }
             '0'...'9' => self.read_int(true, true),
             _ => {
                 self.finished = true;
@@ -510,6 +628,8 @@ impl<'a> Tokens<'a> {
 /// A syntactical part of a document in the TOML format.
 #[derive(Debug, Clone, Copy)]
 pub enum Token<'a> {
+    /// A byte order mark (`U+FEFF`) at the start of the document.
+    ByteOrderMark,
     /// A sequence of TOML whitespace (space or tab characters).
     Whitespace(&'a str),
     /// `[`
@@ -558,11 +678,17 @@ pub enum Token<'a> {
         /// Whether this is a `"""multi-line string"""`
         multiline: bool,
     },
-    /// A datetime object. In space-toml this is just read as a string.
-    DateTime(&'a str),
+    /// A datetime with an offset from UTC, eg `1979-05-27T07:32:00Z`
+    OffsetDateTime(&'a str),
+    /// A datetime without an offset, eg `1979-05-27T07:32:00`
+    LocalDateTime(&'a str),
+    /// A date, eg `1979-05-27`
+    LocalDate(&'a str),
+    /// A time of day, eg `07:32:00`
+    LocalTime(&'a str),
     /// An integer, eg `5` or `-5`
     Int(&'a str),
-    /// A floating-point number, eg `0.5` or `5e-5`
+    /// A floating-point number, eg `0.5`, `5e-5`, `-inf` or `nan`
     Float(&'a str),
     /// `true` or `false`
     Bool(bool),
@@ -573,13 +699,13 @@ impl<'a> Token<'a> {
     pub fn write(&self, out: &mut String) {
         use self::Token::*;
         match *self {
-            Whitespace(s) | Newline(s) | PlainKey(s) | DateTime(s) | Int(s) | Float(s) => {
-                out.push_str(s)
-            }
+            Whitespace(s) | Newline(s) | PlainKey(s) | OffsetDateTime(s) | LocalDateTime(s) |
+            LocalDate(s) | LocalTime(s) | Int(s) | Float(s) => out.push_str(s),
             Comment(s) => {
                 out.push('#');
                 out.push_str(s);
             }
+            ByteOrderMark => out.push('\u{FEFF}'),
             SingleBracketOpen => out.push_str("["),
             DoubleBracketOpen => out.push_str("[["),
             SingleBracketClose => out.push_str("]"),
@@ -678,6 +804,34 @@ pub enum ErrorKind {
         /// The byte index of the invalid unicode escape code.
         pos: usize,
     },
+    /// A datetime, date or time was malformed, or had an out-of-range part.
+    InvalidDateTime {
+        /// The byte index of the datetime
+        start: usize,
+        /// The byte index of the invalid date, time or offset part
+        pos: usize,
+    },
+}
+
+impl ErrorKind {
+    /// Returns the name of this kind of error, like `UnclosedString`.
+    pub fn name(&self) -> &'static str {
+        use self::ErrorKind::*;
+        match *self {
+            InvalidWhitespace { .. } => "InvalidWhitespace",
+            UnclosedLiteral { .. } => "UnclosedLiteral",
+            UnclosedString { .. } => "UnclosedString",
+            UnmatchedClosingBrace { .. } => "UnmatchedClosingBrace",
+            InvalidKeyCharacter { .. } => "InvalidKeyCharacter",
+            InvalidValueCharacter { .. } => "InvalidValueCharacter",
+            InvalidIntCharacter { .. } => "InvalidIntCharacter",
+            InvalidEscapeCharacter { .. } => "InvalidEscapeCharacter",
+            InvalidFloatCharacter { .. } => "InvalidFloatCharacter",
+            UnderscoreNotAfterNumber { .. } => "UnderscoreNotAfterNumber",
+            InvalidUnicode { .. } => "InvalidUnicode",
+            InvalidDateTime { .. } => "InvalidDateTime",
+        }
+    }
 }
 
 /// An error found when lexing a TOML document.
@@ -689,66 +843,73 @@ pub struct Error<'a> {
     pub text: &'a str,
 }
 
+impl<'a> Error<'a> {
+    /// Returns the byte range of the text that the error points at: the invalid
+    /// character, or the rest of the text from the start of an unclosed string.
+    pub fn span(&self) -> Range<usize> {
+        use self::ErrorKind::*;
+        match self.kind {
+            UnclosedLiteral { start } |
+            UnclosedString { start } => start..self.text.len(),
+            InvalidWhitespace { pos } |
+            UnmatchedClosingBrace { pos } |
+            InvalidKeyCharacter { pos } |
+            InvalidValueCharacter { pos, .. } |
+            InvalidIntCharacter { pos, .. } |
+            InvalidEscapeCharacter { pos, .. } |
+            InvalidFloatCharacter { pos, .. } |
+            UnderscoreNotAfterNumber { pos, .. } |
+            InvalidDateTime { pos, .. } |
+            InvalidUnicode { pos } => debug::char_span(self.text, pos),
+        }
+    }
+
+    /// Returns the message of the error without the excerpt of the text, like
+    /// `Invalid key character at 1:2`.
+    pub fn message(&self) -> String {
+        use self::ErrorKind::*;
+        let pos = self.span().start;
+        let (line, col) = debug::get_position(self.text, pos);
+        let what = match self.kind {
+            UnclosedString { .. } |
+            UnclosedLiteral { .. } => "Unclosed string starting",
+            InvalidEscapeCharacter { .. } => {
+                return match self.text[pos..].chars().next() {
+                    Some(ch) if self.text[..pos].ends_with('\\') => {
+                        format!("Invalid escape '\\{}' at {}:{}", ch.escape_default(), line, col)
+                    }
+                    Some(ch) => {
+                        format!("Invalid character '{}' in escape at {}:{}",
+                                ch.escape_default(),
+                                line,
+                                col)
+                    }
+                    None => format!("Unfinished escape at {}:{}", line, col),
+                };
+            }
+            InvalidValueCharacter { .. } => "Invalid character in value",
+            InvalidIntCharacter { .. } => "Invalid character in integer",
+            InvalidFloatCharacter { .. } => "Invalid character in float",
+            UnmatchedClosingBrace { .. } => "Unmatched brace found",
+            InvalidKeyCharacter { .. } => "Invalid key character",
+            InvalidWhitespace { .. } => "Invalid whitespace character",
+            UnderscoreNotAfterNumber { .. } => "Underscore not after number",
+            InvalidUnicode { .. } => "Invalid unicode escape value",
+            InvalidDateTime { .. } => "Invalid datetime",
+        };
+        format!("{} at {}:{}", what, line, col)
+    }
+}
+
 impl<'a> fmt::Display for Error<'a> {
     /// Writes a longer error message to the given output.
     fn fmt(&self, output: &mut fmt::Formatter) -> fmt::Result {
         use self::ErrorKind::*;
+        write!(output, "{} :", self.message())?;
         match self.kind {
-            UnclosedString { start } => {
-                let (line, col) = debug::get_position(self.text, start);
-                write!(output, "Unclosed string starting at {}:{} :", line, col)?;
-                debug::write_unclosed(self.text, start, output)
-            }
-            UnclosedLiteral { start } => {
-                let (line, col) = debug::get_position(self.text, start);
-                write!(output, "Unclosed string starting at {}:{} :", line, col)?;
-                debug::write_unclosed(self.text, start, output)
-            }
-            InvalidEscapeCharacter { pos, .. } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid escape character at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidValueCharacter { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid character in value at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidIntCharacter { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid character in integer at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidFloatCharacter { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid character in float at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            UnmatchedClosingBrace { pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Unmatched brace found at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidKeyCharacter { pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid key character at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidWhitespace { pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid whitespace character at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            UnderscoreNotAfterNumber { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Underscore not after number at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
-            InvalidUnicode { pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                write!(output, "Invalid unicode escape value at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, output)
-            }
+            UnclosedString { start } |
+            UnclosedLiteral { start } => debug::write_unclosed(self.text, start, output),
+            _ => debug::write_invalid_character(self.text, self.span().start, output),
         }
     }
 }
@@ -773,6 +934,10 @@ impl<'a> Iterator for Tokens<'a> {
 
         if let Some((i, ch)) = self.chars.next() {
             match ch {
+                '\u{FEFF}' if i == 0 => {
+                    self.start += ch.len_utf8();
+                    return Some(Ok((start, ByteOrderMark)));
+                }
                 ' ' | '\t' => {
                     return Some(self.read_whitespace());
                 }
@@ -794,7 +959,7 @@ impl<'a> Iterator for Tokens<'a> {
                 '}' => {
                     if self.scope_stack.is_empty() {
                         self.finished = true;
-                        return Some(self.err(UnmatchedClosingBrace { pos: self.start - 1 }));
+                        return Some(self.err(UnmatchedClosingBrace { pos: i }));
                     } else {
                         self.scope_stack.pop();
                     }
@@ -802,11 +967,10 @@ impl<'a> Iterator for Tokens<'a> {
                     return Some(Ok((start, CurlyClose)));
                 }
                 '\r' => {
-                    self.start += 1;
                     if self.peek_is('\n') {
                         self.chars.next();
-                        let part = &self.text[self.start..self.start + 2];
-                        self.start += 1;
+                        let part = &self.text[i..i + 2];
+                        self.start = i + 2;
                         // New line, new key
                         if self.scope_stack.is_empty() {
                             self.scope = LexerScope::Key;
@@ -855,7 +1019,9 @@ impl<'a> Iterator for Tokens<'a> {
                         }
                         LexerScope::Key => {
                             match ch {
-                                'a'...'z' | 'A'...'Z' | '_' | '-' => return Some(self.read_key()),
+                                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => {
+                                    return Some(self.read_key());
+                                }
                                 _ => {
                                     return Some(self.err(InvalidKeyCharacter { pos: i }));
                                 }
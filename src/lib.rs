@@ -1,4 +1,3 @@
-#![feature(slice_patterns)]
 #![deny(missing_docs)]
 //! Parses and edits TOML documents while preserving the formatting of the original document.
 #[macro_use]
@@ -22,15 +21,31 @@ mod tabledata;
 mod table;
 mod array;
 mod value;
+mod datetime;
 mod document;
+mod schema;
+mod trivia;
+mod error;
+mod builder;
+#[cfg(feature = "units")]
+mod units;
 
 pub use lexer::{tokens, Tokens, Token};
 /// An error found when lexing a TOML document.
 pub type LexError<'a> = lexer::Error<'a>;
 /// The kinds of errors that can be found when lexing a TOML document.
 pub type LexerErrorKind = lexer::ErrorKind;
-pub use document::{Document};
-pub use tabledata::CreatePathError;
+pub use document::{Document, Change, PathPart, InsertTableError, IncludeError};
+pub use tabledata::{TableData, CreatePathError, DocumentedEntries, Entry};
 pub use table::{Table};
-pub use value::{Value, Int, Float, TomlString};
-pub use parse::{parse, Error, ErrorKind, Result};
+pub use builder::TableBuilder;
+pub use array::ArrayData;
+pub use value::{Value, Int, Radix, RadixInt, NegativeRadixInt, Float, FloatFormat, TomlString,
+                StringStyle, TomlData, TomlType};
+pub use datetime::{Datetime, Date, Time, Offset};
+pub use schema::{Schema, SchemaError};
+pub use trivia::{Trivia, Attachment};
+pub use utils::{key_needs_quoting, quote_path_segment, join_path};
+pub use error::TomlError;
+pub use parse::{parse, parse_with, parse_prefix, parse_until, parse_inline_table, parse_value,
+                ParseOptions, Error, ErrorKind, Diagnostic, Result};
@@ -3,6 +3,8 @@ use std::iter::{Iterator, Peekable};
 use std::fmt;
 use std::result;
 use std::error;
+use std::collections::HashMap;
+use std::ops::Range;
 
 use lexer::{self, Token, Tokens};
 use document::{Document, DocumentPrivate};
@@ -14,9 +16,119 @@ use array::ArrayData;
 use value::{Value, ValuePrivate};
 use debug;
 
+/// Options for how strictly TOML documents are parsed.
+/// The default options preserve everything that is valid TOML.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Whether whitespace at the end of a line is reported as a `TrailingWhitespace`
+    /// error instead of being preserved.
+    pub deny_trailing_whitespace: bool,
+    /// Whether a key defined twice in the same table is reported as a `KeyDefinedTwice`
+    /// error instead of replacing the earlier value.
+    pub deny_duplicate_keys: bool,
+    /// Whether a table header used twice is reported as a `TableDefinedTwice` error
+    /// instead of adding to the same table.
+    pub deny_duplicate_tables: bool,
+    /// Whether numbers with leading zeros, like `007`, are reported as a `LeadingZero`
+    /// error.
+    pub deny_leading_zeros: bool,
+    /// Whether newlines in single-line strings are reported as a `NewlineInString`
+    /// error.
+    pub deny_newlines_in_strings: bool,
+    /// Whether tabs in the whitespace of table headers, like `[\ta\t]`, are reported as a
+    /// `TabInHeader` error. This is a style lint, so it isn't enabled by `strict`.
+    pub deny_tabs_in_headers: bool,
+}
+
+impl ParseOptions {
+    /// Creates the default parse options.
+    pub fn new() -> ParseOptions {
+        ParseOptions::default()
+    }
+
+    /// Creates options that report every violation of the TOML spec that is otherwise
+    /// accepted: duplicate keys, duplicate tables, leading zeros and newlines in
+    /// single-line strings.
+    /// Trailing whitespace is valid TOML, so it is still preserved. Arrays of mixed
+    /// types are always rejected.
+    pub fn strict() -> ParseOptions {
+        ParseOptions {
+            deny_trailing_whitespace: false,
+            deny_duplicate_keys: true,
+            deny_duplicate_tables: true,
+            deny_leading_zeros: true,
+            deny_newlines_in_strings: true,
+            deny_tabs_in_headers: false,
+        }
+    }
+}
+
 /// Parses the given text as a TOML document and returns the top-level table for the document.
 pub fn parse<'a>(text: &'a str) -> Result<'a, Document<'a>> {
-    Parser::new(text).parse()
+    parse_with(text, &ParseOptions::default())
+}
+
+/// Parses the given text as a TOML document using the given options.
+pub fn parse_with<'a>(text: &'a str, options: &ParseOptions) -> Result<'a, Document<'a>> {
+    Parser::new(text, *options).parse()
+}
+
+/// Parses the given text as a TOML document until the table at the given path and the
+/// tables below it have been read, stopping at the next header without reading the
+/// rest of the text. The partial document holds that table along with the content
+/// before it. Returns `None` if the text has no header for the table or one below it.
+/// Note: Tables below the table that come after other tables are not read.
+pub fn parse_until<'a>(text: &'a str, path: &[&str]) -> Result<'a, Option<Document<'a>>> {
+    let (document, found) = Parser::new(text, ParseOptions::default()).parse_sections(Some(path))?;
+    Ok(if found { Some(document) } else { None })
+}
+
+/// Parses the given text as an inline table, like `{ a = 1, b = 2 }`.
+/// Whitespace around the table is ignored, but any other content is an error.
+pub fn parse_inline_table<'a>(text: &'a str) -> Result<'a, TableData<'a>> {
+    Parser::new(text, ParseOptions::default()).parse_inline_table()
+}
+
+/// Parses the given text as a single value, like `42` or `[1, 2]`.
+/// Whitespace around the value is ignored, but any other content is an error.
+pub fn parse_value<'a>(text: &'a str) -> Result<'a, Value<'a>> {
+    let mut parser = Parser::new(text, ParseOptions::default());
+    parser.tokens = lexer::value_tokens(text).peekable();
+    parser.parse_value()
+}
+
+/// Parses the TOML document at the start of the given text, stopping at the first line
+/// that consists of the given terminator (such as `+++` after front-matter).
+/// Returns the document and the byte index at which it stopped, which is the start of
+/// the terminator line, or the length of the text if none was found.
+/// Lines inside multiline strings are never taken as the terminator.
+pub fn parse_prefix<'a>(text: &'a str, terminator: &str) -> Result<'a, (Document<'a>, usize)> {
+    let is_terminator = |start: usize| {
+        let line = text[start..].split('\n').next().unwrap_or("");
+        let line = if line.ends_with('\r') { &line[..line.len() - 1] } else { line };
+        line == terminator
+    };
+    let mut end = text.len();
+    if is_terminator(0) {
+        end = 0;
+    } else {
+        // Lines only start after newline tokens, which are never inside strings
+        for token in lexer::tokens(text) {
+            match token {
+                Ok((pos, Token::Newline(newline))) => {
+                    if is_terminator(pos + newline.len()) {
+                        end = pos + newline.len();
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                // The error is found again when parsing the text
+                Err(_) => break,
+            }
+        }
+    }
+    let document = parse(&text[..end])?;
+    Ok((document, end))
 }
 
 /// The kinds of errors found when parsing TOML documents.
@@ -31,6 +143,11 @@ pub enum ErrorKind<'a> {
         /// The byte index of the invalid part/token
         pos: usize,
     },
+    /// This table or table array scope contains no keys, eg. `[]`.
+    EmptyScope {
+        /// The byte index of the scope ([)
+        pos: usize,
+    },
     /// The scope starting here wasn't completed.
     UnfinishedScope {
         /// The byte index of the scope
@@ -96,6 +213,34 @@ pub enum ErrorKind<'a> {
     },
     /// This path is invalid (?).
     InvalidScopePath,
+    /// A number starts with a zero followed by more digits (only reported when denied
+    /// by the parse options).
+    LeadingZero {
+        /// The byte index of the number
+        pos: usize,
+    },
+    /// A single-line string contains a newline (only reported when denied by the parse
+    /// options).
+    NewlineInString {
+        /// The byte index of the newline
+        pos: usize,
+    },
+    /// The value of an inline table entry contains a newline, like a multiline string.
+    /// Inline tables must be written on a single line.
+    NewlineInInlineTable {
+        /// The byte index of the value
+        pos: usize,
+    },
+    /// A table header contains a tab (only reported when denied by the parse options).
+    TabInHeader {
+        /// The byte index of the tab
+        pos: usize,
+    },
+    /// A line ends with whitespace (only reported when denied by the parse options).
+    TrailingWhitespace {
+        /// The byte index of the whitespace
+        pos: usize,
+    },
     /// A comma was found before any values.
     NonFinalComma {
         /// The byte index of the comma.
@@ -108,9 +253,80 @@ pub enum ErrorKind<'a> {
         start: usize,
         /// The byte index of the invalid value
         pos: usize,
-        /// A message about the type error.
+        /// A message about the type error, like `array of integers, found string`.
         message: String,
     },
+    /// An array of tables and an inline array were defined with the same key,
+    /// eg. `a = []` followed by `[[a]]`.
+    ArrayKindConflict {
+        /// The byte index of the second definition
+        pos: usize,
+        /// The byte index of the original definition
+        original: usize,
+    },
+    /// An integer doesn't fit in the range of a 64-bit signed integer.
+    IntegerOutOfRange {
+        /// The byte index of the integer
+        pos: usize,
+    },
+    /// A table header has a dot without a key on one of its sides, like `[a.]`, `[.a]`
+    /// or `[a..b]`.
+    EmptyKeySegment {
+        /// The byte index of the header ([)
+        start: usize,
+        /// The byte index of the dot or closing bracket after the missing key
+        pos: usize,
+    },
+}
+
+impl<'a> ErrorKind<'a> {
+    /// Returns the name of this kind of error, like `MissingEquals`. Lexing errors
+    /// give the name of the lexer error, like `UnclosedString`.
+    pub fn name(&self) -> &'static str {
+        use self::ErrorKind::*;
+        match *self {
+            Lex(ref err) => err.kind.name(),
+            InvalidScope { .. } => "InvalidScope",
+            EmptyScope { .. } => "EmptyScope",
+            UnfinishedScope { .. } => "UnfinishedScope",
+            UnfinishedItem { .. } => "UnfinishedItem",
+            UnfinishedValue { .. } => "UnfinishedValue",
+            InvalidValue { .. } => "InvalidValue",
+            MissingEquals { .. } => "MissingEquals",
+            DoubleCommaInArray { .. } => "DoubleCommaInArray",
+            MissingComma { .. } => "MissingComma",
+            InvalidTableItem { .. } => "InvalidTableItem",
+            TableDefinedTwice { .. } => "TableDefinedTwice",
+            KeyDefinedTwice { .. } => "KeyDefinedTwice",
+            InvalidScopePath => "InvalidScopePath",
+            LeadingZero { .. } => "LeadingZero",
+            NewlineInString { .. } => "NewlineInString",
+            NewlineInInlineTable { .. } => "NewlineInInlineTable",
+            TabInHeader { .. } => "TabInHeader",
+            TrailingWhitespace { .. } => "TrailingWhitespace",
+            NonFinalComma { .. } => "NonFinalComma",
+            WrongValueTypeInArray { .. } => "WrongValueTypeInArray",
+            ArrayKindConflict { .. } => "ArrayKindConflict",
+            IntegerOutOfRange { .. } => "IntegerOutOfRange",
+            EmptyKeySegment { .. } => "EmptyKeySegment",
+        }
+    }
+}
+
+/// A machine-readable description of a parse error, for tools that report errors as
+/// data, like editors or CI annotations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The name of the kind of error, like `MissingEquals`.
+    pub kind: &'static str,
+    /// The message of the error without the excerpt of the text, like `'=' expected at 1:3`.
+    pub message: String,
+    /// The 1-indexed line of the start of the span.
+    pub line: Option<usize>,
+    /// The 1-indexed column (in characters) of the start of the span.
+    pub col: Option<usize>,
+    /// The byte range of the text that the error points at.
+    pub span: Option<Range<usize>>,
 }
 
 /// An error found when parsing a TOML document.
@@ -129,70 +345,140 @@ impl<'a> Error<'a> {
             text: text,
         }
     }
-}
 
-// TODO: make this a different function again
-impl<'a> fmt::Display for Error<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    /// Returns the byte range of the text that the error points at: the invalid
+    /// character, or the rest of the text from the start of an unfinished item.
+    /// Lexing errors give the span of the lexer error. Returns `None` for errors that
+    /// don't point at the text.
+    pub fn span(&self) -> Option<Range<usize>> {
+        use self::ErrorKind::*;
+        let pos = match self.kind {
+            Lex(ref err) => return Some(err.span()),
+            UnfinishedScope { start } |
+            UnfinishedItem { start } |
+            UnfinishedValue { start } => return Some(start..self.text.len()),
+            InvalidScopePath => return None,
+            InvalidScope { pos, .. } |
+            EmptyScope { pos } |
+            InvalidValue { pos, .. } |
+            MissingEquals { pos, .. } |
+            DoubleCommaInArray { pos, .. } |
+            MissingComma { pos, .. } |
+            InvalidTableItem { pos } |
+            TableDefinedTwice { pos, .. } |
+            KeyDefinedTwice { pos, .. } |
+            LeadingZero { pos } |
+            NewlineInString { pos } |
+            NewlineInInlineTable { pos } |
+            TabInHeader { pos } |
+            TrailingWhitespace { pos } |
+            NonFinalComma { pos } |
+            WrongValueTypeInArray { pos, .. } |
+            ArrayKindConflict { pos, .. } |
+            IntegerOutOfRange { pos } |
+            EmptyKeySegment { pos, .. } => pos,
+        };
+        Some(debug::char_span(self.text, pos))
+    }
+
+    /// Returns the message of the error without the excerpt of the text, like
+    /// `'=' expected at 1:3`.
+    pub fn message(&self) -> String {
         use self::ErrorKind::*;
+        let at = |pos| {
+            let (line, col) = debug::get_position(self.text, pos);
+            format!("{}:{}", line, col)
+        };
         match self.kind {
-            Lex(ref err) => err.fmt(f),
-            InvalidScope { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "Invalid scope found at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
-            }
-            UnfinishedScope { start } => {
-                let (line, col) = debug::get_position(self.text, start);
-                writeln!(f, "Unifinished scope starting at {}:{} :", line, col)?;
-                debug::write_unclosed(self.text, start, f)
+            Lex(ref err) => err.message(),
+            InvalidScope { pos, .. } => format!("Invalid scope found at {}", at(pos)),
+            EmptyScope { pos } => format!("Empty table header at {}", at(pos)),
+            UnfinishedScope { start } => format!("Unifinished scope starting at {}", at(start)),
+            UnfinishedItem { start } => format!("No value found for key at {}", at(start)),
+            UnfinishedValue { start } => format!("Unifinished value starting at {}", at(start)),
+            MissingEquals { pos, .. } => format!("'=' expected at {}", at(pos)),
+            InvalidValue { pos, .. } => format!("Invalid value found at {}", at(pos)),
+            DoubleCommaInArray { pos, .. } => format!("Invalid comma in array at {}", at(pos)),
+            MissingComma { pos, .. } => format!("Expected comma in array at {}", at(pos)),
+            InvalidTableItem { pos } => format!("Invalid top_level item found at {}", at(pos)),
+            WrongValueTypeInArray { ref message, pos, .. } => {
+                format!("Expected an {} at {}", message, at(pos))
             }
-            UnfinishedItem { start } => {
-                let (line, col) = debug::get_position(self.text, start);
-                writeln!(f, "No value found for key at {}:{} :", line, col)?;
-                debug::write_unclosed(self.text, start, f)
+            TableDefinedTwice { pos, original } => {
+                format!("Table at {} was already defined at {}", at(pos), at(original))
             }
-            UnfinishedValue { start } => {
-                let (line, col) = debug::get_position(self.text, start);
-                writeln!(f, "Unifinished value starting at {}:{} :", line, col)?;
-                debug::write_unclosed(self.text, start, f)
+            KeyDefinedTwice { pos, original } => {
+                format!("Key at {} was already defined at {}", at(pos), at(original))
             }
-            MissingEquals { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "'=' expected at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
-            }
-            InvalidValue { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "Invalid value found at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
-            }
-            DoubleCommaInArray { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "Invalid comma in array at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
-            }
-            MissingComma { start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "Expected comma in array at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
+            ArrayKindConflict { pos, original } => {
+                format!("Array at {} conflicts with the array of another kind defined at {}",
+                        at(pos),
+                        at(original))
             }
-            InvalidTableItem { pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f, "Invalid top_level item found at {}:{} :", line, col)?;
-                debug::write_invalid_character(self.text, pos, f)
+            InvalidScopePath => "Invalid table path".to_string(),
+            LeadingZero { pos } => format!("Number with leading zero at {}", at(pos)),
+            NewlineInString { pos } => format!("Newline in single-line string at {}", at(pos)),
+            NewlineInInlineTable { pos } => {
+                format!("Newline in inline table value at {}", at(pos))
             }
-            WrongValueTypeInArray { ref message, start: _start, pos } => {
-                let (line, col) = debug::get_position(self.text, pos);
-                writeln!(f,
-                         "Value of invalid type found in array at {}:{} :",
-                         line,
-                         col)?;
-                writeln!(f, "{}", message)?;
-                debug::write_invalid_character(self.text, pos, f)
+            TabInHeader { pos } => format!("Tab in table header at {}", at(pos)),
+            TrailingWhitespace { pos } => format!("Trailing whitespace at {}", at(pos)),
+            NonFinalComma { pos } => format!("Comma before any values at {}", at(pos)),
+            IntegerOutOfRange { pos } => format!("Integer out of range at {}", at(pos)),
+            EmptyKeySegment { pos, .. } => {
+                format!("Empty key segment in table header at {}", at(pos))
             }
+        }
+    }
+
+    /// Returns a structured description of the error. The position fields are `None`
+    /// for errors that don't point at the text.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        let position = span.as_ref().map(|span| debug::get_position(self.text, span.start));
+        Diagnostic {
+            kind: self.kind.name(),
+            message: self.message(),
+            line: position.map(|(line, _)| line),
+            col: position.map(|(_, col)| col),
+            span: span,
+        }
+    }
+}
+
+/// Returns whether parsing should stop before the header with the given path, which is
+/// after the table at the path to stop after and the tables below it were read.
+/// Marks the table as found when the header is for it, or for a table below it.
+fn is_past_table(header: &[Key], stop_after: Option<&[&str]>, found: &mut bool) -> bool {
+    let path = match stop_after {
+        Some(path) => path,
+        None => return false,
+    };
+    let is_below = header.len() >= path.len() &&
+                   path.iter().zip(header).all(|(key, other)| other.normalized() == *key);
+    if is_below {
+        *found = true;
+    }
+    *found && !is_below
+}
+
+// TODO: make this a different function again
+impl<'a> fmt::Display for Error<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ErrorKind::*;
+        match self.kind {
+            Lex(ref err) => return err.fmt(f),
+            InvalidScopePath => return writeln!(f, "{}", self.message()),
+            _ => {}
+        }
+        writeln!(f, "{} :", self.message())?;
+        match self.kind {
+            UnfinishedScope { start } |
+            UnfinishedItem { start } |
+            UnfinishedValue { start } => debug::write_unclosed(self.text, start, f),
             _ => {
-                unimplemented!();
+                let pos = self.span().map_or(0, |span| span.start);
+                debug::write_invalid_character(self.text, pos, f)
             }
         }
     }
@@ -220,12 +506,23 @@ pub type Result<'a, T> = result::Result<T, Error<'a>>;
 struct Parser<'a> {
     text: &'a str,
     tokens: Peekable<Tokens<'a>>,
+    /// The byte index at which the value at each key path was last defined.
+    definitions: HashMap<Vec<Key<'a>>, usize>,
+    /// The byte index of the header of each table in the current scope.
+    tables: HashMap<Vec<Key<'a>>, usize>,
+    /// The byte index of the value of the last item that was read.
+    value_start: usize,
+    options: ParseOptions,
 }
 impl<'a> Parser<'a> {
-    fn new(text: &'a str) -> Parser<'a> {
+    fn new(text: &'a str, options: ParseOptions) -> Parser<'a> {
         Parser {
             text: text,
             tokens: lexer::tokens(text).peekable(),
+            definitions: HashMap::new(),
+            tables: HashMap::new(),
+            value_start: 0,
+            options: options,
         }
     }
 
@@ -246,7 +543,7 @@ impl<'a> Parser<'a> {
             match token {
                 Dot => {
                     if !was_key {
-                        return self.err(InvalidScope {
+                        return self.err(EmptyKeySegment {
                             start: start,
                             pos: pos,
                         });
@@ -256,8 +553,11 @@ impl<'a> Parser<'a> {
                     }
                 }
                 SingleBracketClose if !array => {
-                    if (!key_found) || (!was_key) {
-                        return self.err(InvalidScope {
+                    if !key_found {
+                        return self.err(EmptyScope { pos: start });
+                    }
+                    if !was_key {
+                        return self.err(EmptyKeySegment {
                             start: start,
                             pos: pos,
                         });
@@ -266,8 +566,11 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 DoubleBracketClose if array => {
-                    if (!key_found) || (!was_key) {
-                        return self.err(InvalidScope {
+                    if !key_found {
+                        return self.err(EmptyScope { pos: start });
+                    }
+                    if !was_key {
+                        return self.err(EmptyKeySegment {
                             start: start,
                             pos: pos,
                         });
@@ -276,6 +579,11 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 Whitespace(text) => {
+                    if self.options.deny_tabs_in_headers {
+                        if let Some(index) = text.find('\t') {
+                            return self.err(TabInHeader { pos: pos + index });
+                        }
+                    }
                     scope.push_space(text);
                 }
                 PlainKey(text) => {
@@ -284,6 +592,7 @@ impl<'a> Parser<'a> {
                     scope.push_key(Key::from_key(text));
                 }
                 String { text, literal, multiline } => {
+                    self.check_string(pos, text, multiline)?;
                     key_found = true;
                     was_key = true;
                     scope.push_key(Key::from_string(text, literal, multiline));
@@ -309,7 +618,6 @@ impl<'a> Parser<'a> {
         trace!("Reading array");
         let mut array = ArrayData::new_inline();
         let mut is_reading_value = true;
-        let mut was_comma = false;
         loop {
             if is_reading_value {
                 match self.peek_or(UnfinishedItem { start: start })? {
@@ -318,10 +626,8 @@ impl<'a> Parser<'a> {
                         break;
                     }
                     (pos, Comma) => {
-                        if !was_comma {
-                            self.tokens.next();
-                            array.push_comma();
-                            was_comma = true;
+                        if array.is_empty() {
+                            return self.err(NonFinalComma { pos: pos });
                         } else {
                             return self.err(DoubleCommaInArray {
                                 start: start,
@@ -339,20 +645,18 @@ impl<'a> Parser<'a> {
                         array.push_comment(text);
                     }
                     (pos, _) => {
-                        if was_comma {
-                            return self.err(NonFinalComma { pos: pos });
-                        }
                         let value = self.read_value(start)?;
-                        match array.push_value(value) {
-                            Ok(_) => {}
-                            Err(message) => {
-                                return self.err(WrongValueTypeInArray {
-                                    start: start,
-                                    pos: pos,
-                                    message: message,
-                                });
-                            }
+                        if !array.can_insert_type(&value) {
+                            let message = format!("array of {}s, found {}",
+                                                  array.get(0).unwrap().type_name(),
+                                                  value.type_name());
+                            return self.err(WrongValueTypeInArray {
+                                start: start,
+                                pos: pos,
+                                message: message,
+                            });
                         }
+                        array.push_value(value).expect("The type was checked");
                         is_reading_value = false;
                     }
                 }
@@ -395,6 +699,7 @@ impl<'a> Parser<'a> {
         trace!("Reading inline table");
         let mut reading_key = true;
         let mut was_comma = false;
+        let mut keys = HashMap::new();
         while let Some(res) = self.tokens.next() {
             if reading_key {
                 let res = res?;
@@ -415,22 +720,25 @@ impl<'a> Parser<'a> {
                     (pos, PlainKey(text)) => {
                         let key = Key::Plain(text);
                         let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                        // TODO: Check for duplicate keys
-                        table.insert_spaced(key, value, before_eq, after_eq);
+                        self.check_inline_key(&mut keys, key, pos)?;
+                        self.check_inline_value()?;
+                        table.insert_read(key, value, before_eq, after_eq, pos);
                         reading_key = false;
                     }
                     (pos, String { text, literal, multiline }) => {
                         if was_comma {
                             return self.err(NonFinalComma { pos: pos });
                         }
+                        self.check_string(pos, text, multiline)?;
                         let key = Key::String {
                             text: text,
                             literal: literal,
                             multiline: multiline,
                         };
                         let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                        // TODO: Check for duplicate keys
-                        table.insert_spaced(key, value, before_eq, after_eq);
+                        self.check_inline_key(&mut keys, key, pos)?;
+                        self.check_inline_value()?;
+                        table.insert_read(key, value, before_eq, after_eq, pos);
                         reading_key = false;
                     }
                     (_, CurlyClose) => {
@@ -470,11 +778,28 @@ impl<'a> Parser<'a> {
         trace!("Reading value");
         let next = self.next_or(UnfinishedValue { start: start })?;
         let value = match next {
-            (_, Int(text)) => Value::new_int(text),
-            (_, Float(text)) => Value::new_float(text),
-            (_, String { text, literal, multiline }) => Value::new_string(text, literal, multiline),
+            (pos, Int(text)) => {
+                self.check_number(pos, text)?;
+                match Value::new_int(text) {
+                    Value::Int(ref int) if int.as_i64().is_none() => {
+                        return self.err(IntegerOutOfRange { pos: pos });
+                    }
+                    value => value,
+                }
+            }
+            (pos, Float(text)) => {
+                self.check_number(pos, text)?;
+                Value::new_float(text)
+            }
+            (pos, String { text, literal, multiline }) => {
+                self.check_string(pos, text, multiline)?;
+                Value::new_string(text, literal, multiline)
+            }
             (_, Bool(value)) => Value::new_bool(value),
-            (_, DateTime(text)) => Value::new_datetime(text),
+            (_, OffsetDateTime(text)) |
+            (_, LocalDateTime(text)) |
+            (_, LocalDate(text)) |
+            (_, LocalTime(text)) => Value::new_datetime(text),
             (pos, SingleBracketOpen) => self.read_array(pos)?,
             (pos, CurlyOpen) => {
                 let mut table = TableData::new_inline();
@@ -528,6 +853,7 @@ impl<'a> Parser<'a> {
 
         let value_start = self.peek_or(UnfinishedItem { start: start })?.0;
         let value = self.read_value(value_start)?;
+        self.value_start = value_start;
         trace!("Read item ({:?} = {:?})", key, value);
         Ok((key, before_eq, after_eq, value))
     }
@@ -549,7 +875,135 @@ impl<'a> Parser<'a> {
         self.err(err)
     }
 
-    fn read_table(&mut self, table: &mut TableData<'a>) -> Result<'a, ()> {
+    /// Returns an error for the first whitespace token that ends a line.
+    fn check_trailing_whitespace(&mut self) -> Result<'a, ()> {
+        use lexer::Token::*;
+        let mut space = None;
+        for token in lexer::tokens(self.text) {
+            match token? {
+                (pos, Whitespace(_)) => space = Some(pos),
+                (_, Newline(_)) => {
+                    if let Some(pos) = space {
+                        return self.err(ErrorKind::TrailingWhitespace { pos: pos });
+                    }
+                }
+                _ => space = None,
+            }
+        }
+        Ok(())
+    }
+
+    /// Records where the value at the given path was defined.
+    fn define(&mut self, path: &[Key<'a>], key: &Key<'a>, pos: usize) {
+        let mut path = path.to_vec();
+        path.push(key.clone());
+        self.definitions.insert(path, pos);
+    }
+
+    /// Returns where the value at the given path was last defined.
+    fn definition(&self, path: &[Key<'a>], key: &Key<'a>) -> Option<usize> {
+        let mut path = path.to_vec();
+        path.push(key.clone());
+        self.definitions.get(&path).cloned()
+    }
+
+    /// Returns an error for a newline in a single-line string, if denied by the options.
+    fn check_string(&mut self, pos: usize, text: &str, multiline: bool) -> Result<'a, ()> {
+        if self.options.deny_newlines_in_strings && !multiline {
+            if let Some(index) = text.find(|c| c == '\n' || c == '\r') {
+                // Skip the quote
+                return self.err(ErrorKind::NewlineInString { pos: pos + 1 + index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error for a key defined twice in an inline table, if denied by the
+    /// options.
+    fn check_inline_key(&mut self,
+                        keys: &mut HashMap<Key<'a>, usize>,
+                        key: Key<'a>,
+                        pos: usize)
+                        -> Result<'a, ()> {
+        if let Some(&original) = keys.get(&key) {
+            if self.options.deny_duplicate_keys {
+                return self.err(ErrorKind::KeyDefinedTwice {
+                    pos: pos,
+                    original: original,
+                });
+            }
+        }
+        keys.insert(key, pos);
+        Ok(())
+    }
+
+    /// Returns an error if the value of the inline table entry that has just been read
+    /// spans several lines.
+    fn check_inline_value(&mut self) -> Result<'a, ()> {
+        let end = match self.tokens.peek() {
+            Some(&Ok((end, _))) => end,
+            _ => self.text.len(),
+        };
+        if self.text[self.value_start..end].contains('\n') {
+            let pos = self.value_start;
+            return self.err(ErrorKind::NewlineInInlineTable { pos: pos });
+        }
+        Ok(())
+    }
+
+    /// Returns an error for a number with a leading zero, if denied by the options.
+    fn check_number(&mut self, pos: usize, text: &str) -> Result<'a, ()> {
+        if self.options.deny_leading_zeros {
+            let digits = if text.starts_with('+') || text.starts_with('-') {
+                &text[1..]
+            } else {
+                text
+            };
+            let mut chars = digits.chars();
+            if chars.next() == Some('0') {
+                if let Some(ch) = chars.next() {
+                    if ch.is_digit(10) || ch == '_' {
+                        return self.err(ErrorKind::LeadingZero { pos: pos });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry read at the given position, checking that it doesn't replace an
+    /// array of tables.
+    fn insert_entry(&mut self,
+                    table: &mut TableData<'a>,
+                    path: &[Key<'a>],
+                    pos: usize,
+                    entry: (Key<'a>, Option<&'a str>, Option<&'a str>, Value<'a>))
+                    -> Result<'a, ()> {
+        let (key, before_eq, after_eq, value) = entry;
+        if let Value::Array(_) = value {
+            if let Some(&Value::Array(ref array)) = table.items.get(&key) {
+                if !array.is_inline() {
+                    let original = self.definition(path, &key).unwrap_or(pos);
+                    return self.err(ErrorKind::ArrayKindConflict {
+                        pos: pos,
+                        original: original,
+                    });
+                }
+            }
+        }
+        if self.options.deny_duplicate_keys && table.items.contains_key(&key) {
+            let original = self.definition(path, &key).unwrap_or(pos);
+            return self.err(ErrorKind::KeyDefinedTwice {
+                pos: pos,
+                original: original,
+            });
+        }
+        self.define(path, &key, pos);
+        table.insert_read(key, value, before_eq, after_eq, pos);
+        Ok(())
+    }
+
+    fn read_table(&mut self, table: &mut TableData<'a>, path: &[Key<'a>]) -> Result<'a, ()> {
         use lexer::Token::*;
         use self::ErrorKind::*;
         trace!("Reading table");
@@ -578,9 +1032,8 @@ impl<'a> Parser<'a> {
                 }
                 (pos, PlainKey(text)) => {
                     let key = Key::Plain(text);
-                    let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                    // TODO: Check for duplicate keys
-                    table.insert_spaced(key, value, before_eq, after_eq);
+                    let entry = self.read_item(pos, key)?;
+                    self.insert_entry(table, path, pos, entry)?;
                 }
                 (pos, String { text, literal, multiline }) => {
                     let key = Key::String {
@@ -588,9 +1041,8 @@ impl<'a> Parser<'a> {
                         literal: literal,
                         multiline: multiline,
                     };
-                    let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                    // TODO: Check for duplicate keys
-                    table.insert_spaced(key, value, before_eq, after_eq);
+                    let entry = self.read_item(pos, key)?;
+                    self.insert_entry(table, path, pos, entry)?;
                 }
                 (pos, _) => {
                     return self.err(InvalidTableItem { pos: pos });
@@ -601,60 +1053,147 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse(mut self) -> Result<'a, Document<'a>> {
+    fn parse_value(mut self) -> Result<'a, Value<'a>> {
         use lexer::Token::*;
         use self::ErrorKind::*;
-        trace!("Parse: Starting...");
-        let mut document = Document::new();
+        if let Some(&Ok((_, Whitespace(_)))) = self.tokens.peek() {
+            self.tokens.next();
+        }
+        let value = self.read_value(0)?;
         while let Some(res) = self.tokens.next() {
             match res? {
-                (_, Whitespace(text)) => {
-                    document.push_space_unchecked(text);
+                (_, Whitespace(_)) |
+                (_, Newline(_)) => {}
+                (pos, _) => {
+                    return self.err(InvalidValue {
+                        start: 0,
+                        pos: pos,
+                    });
                 }
-                (_, Newline(text)) => {
-                    let newline = match text {
-                        "\n" => ::document::Newline::Lf,
-                        "\r\n" => ::document::Newline::CrLf,
-                        _ => unreachable!(),
-                    };
-                    document.push_newline(newline);
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_inline_table(mut self) -> Result<'a, TableData<'a>> {
+        use lexer::Token::*;
+        use self::ErrorKind::*;
+        let mut next = self.next_or(UnfinishedValue { start: 0 })?;
+        if let (_, Whitespace(_)) = next {
+            next = self.next_or(UnfinishedValue { start: 0 })?;
+        }
+        let start = match next {
+            (pos, CurlyOpen) => pos,
+            (pos, _) => {
+                return self.err(InvalidValue {
+                    start: 0,
+                    pos: pos,
+                });
+            }
+        };
+        let mut table = TableData::new_inline();
+        self.read_inline_table(start, &mut table)?;
+        while let Some(res) = self.tokens.next() {
+            match res? {
+                (_, Whitespace(_)) |
+                (_, Newline(_)) => {}
+                (pos, _) => {
+                    return self.err(InvalidValue {
+                        start: start,
+                        pos: pos,
+                    });
                 }
+            }
+        }
+        Ok(table)
+    }
+
+    fn parse(self) -> Result<'a, Document<'a>> {
+        self.parse_sections(None).map(|(document, _)| document)
+    }
+
+    /// Parses the document, stopping before the first header after the table at the
+    /// given path and the tables below it, if a path is given. Returns the document and
+    /// whether a header for the table or one below it was found.
+    fn parse_sections(mut self, stop_after: Option<&[&str]>) -> Result<'a, (Document<'a>, bool)> {
+        use lexer::Token::*;
+        use self::ErrorKind::*;
+        trace!("Parse: Starting...");
+        let mut document = Document::new();
+        let mut found = false;
+        if let Some(&Ok((_, ByteOrderMark))) = self.tokens.peek() {
+            self.tokens.next();
+            document.read_bom();
+        }
+        self.read_table(document.root().data(), &[])?;
+        while let Some(res) = self.tokens.next() {
+            match res? {
                 (pos, SingleBracketOpen) => {
                     let mut scope = Scope::new();
                     self.read_scope(&mut scope, false, pos)?;
-
-                    // TODO: Validate that the scope hasn't been used before
+                    if is_past_table(scope.path(), stop_after, &mut found) {
+                        break;
+                    }
+                    if let Some(&original) = self.tables.get(scope.path()) {
+                        if self.options.deny_duplicate_tables {
+                            return self.err(TableDefinedTwice {
+                                pos: pos,
+                                original: original,
+                            });
+                        }
+                    }
+                    self.tables.insert(scope.path().to_vec(), pos);
                     {
-                        let mut table = match document.find_or_insert_table(scope.path()) {
+                        let mut table = match document.find_or_insert_table_unscoped(scope.path()) {
                             Err(_) => {
                                 return self.err(InvalidScopePath);
                             }
                             Ok(table) => table,
                         };
-                        self.read_table(&mut table.data())?;
+                        self.read_table(&mut table.data(), scope.path())?;
                     }
-                    document.push_table_scope(scope);
+                    document.push_table_scope_unchecked(scope);
                 }
                 (pos, DoubleBracketOpen) => {
                     let mut scope = Scope::new();
                     self.read_scope(&mut scope, true, pos)?;
+                    if is_past_table(scope.path(), stop_after, &mut found) {
+                        break;
+                    }
+                    // Tables below an array of tables belong to its previous element
+                    self.tables.retain(|path, _| !path.starts_with(scope.path()));
                     {
-                        let (last, rest) = scope.path().split_last().unwrap();
+                        let (last, rest) = match scope.path().split_last() {
+                            Some(parts) => parts,
+                            None => return self.err(EmptyScope { pos: pos }),
+                        };
                         let mut table = if !rest.is_empty() {
-                            match document.find_or_insert_table(rest) {
+                            match document.find_or_insert_table_unscoped(rest) {
                                 Ok(table) => table,
                                 Err(_) => {
-                                    //TODO Invalid Scope
-                                    panic!("Invalid Scope");
+                                    return self.err(InvalidScopePath);
                                 }
                             }
                         } else {
                             document.root()
                         };
-                        let mut array = match *table.get_or_insert_with(last.clone(), || {
-                            ArrayData::new_of_tables().into()
-                        }) {
-                            Value::Array(ref mut array) => array,
+                        let array = match *table.data()
+                            .items
+                            .entry(last.clone())
+                            .or_insert_with(|| ArrayData::new_of_tables().into()) {
+                            Value::Array(ref array) if array.is_inline() => {
+                                let original = self.definition(rest, last).unwrap_or(pos);
+                                return self.err(ArrayKindConflict {
+                                    pos: pos,
+                                    original: original,
+                                });
+                            }
+                            Value::Array(ref mut array) => {
+                                if array.len() == 0 {
+                                    self.define(rest, last, pos);
+                                }
+                                array
+                            }
                             _ => {
                                 // TODO: Use different error here?
                                 return self.err(KeyDefinedTwice {
@@ -677,35 +1216,21 @@ impl<'a> Parser<'a> {
                             };
                         let mut table = match *table {
                             Value::Table(ref mut table) => table,
-                            _ => unreachable!(),
+                            _ => return self.err(InvalidScopePath),
                         };
-                        self.read_table(table)?;
+                        self.read_table(table, scope.path())?;
                     }
-                    document.push_array_scope(scope);
-                }
-                (_, Comment(text)) => {
-                    document.push_comment(text);
-                }
-                (pos, PlainKey(text)) => {
-                    let key = Key::Plain(text);
-                    let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                    document.root().insert_spaced(key, value, before_eq, after_eq);
-                }
-                (pos, String { text, literal, multiline }) => {
-                    let key = Key::String {
-                        text: text,
-                        literal: literal,
-                        multiline: multiline,
-                    };
-                    let (key, before_eq, after_eq, value) = self.read_item(pos, key)?;
-                    document.root().insert_spaced(key, value, before_eq, after_eq);
+                    document.push_array_scope_unchecked(scope);
                 }
                 (pos, _) => {
                     return self.err(InvalidTableItem { pos: pos });
                 }
             }
         }
+        if self.options.deny_trailing_whitespace {
+            self.check_trailing_whitespace()?;
+        }
         trace!("Parse: Finished succesfully!");
-        Ok(document)
+        Ok((document, found))
     }
 }
@@ -0,0 +1,70 @@
+use std::fmt;
+use tabledata::TableData;
+
+/// A simple schema for the values of a document, listing the expected type of the
+/// value at each dotted key path, and whether it is required.
+/// The paths are split at every dot, so keys containing dots can't be described.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    entries: Vec<(String, String, bool)>,
+}
+
+/// A value that doesn't match a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    /// A required value was not found at this path.
+    Missing(String),
+    /// The value at this path has the wrong type.
+    WrongType {
+        /// The dotted key path of the value.
+        path: String,
+        /// The type given by the schema.
+        expected: String,
+        /// The type of the value.
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemaError::Missing(ref path) => write!(f, "Missing required value '{}'", path),
+            SchemaError::WrongType { ref path, ref expected, found } => {
+                write!(f, "Expected '{}' to be {}, but found {}", path, expected, found)
+            }
+        }
+    }
+}
+
+impl Schema {
+    /// Creates a schema from a list of dotted key paths, the names of their expected
+    /// types (as given by `Value::type_name`) and whether they are required.
+    /// example: `Schema::new(&[("package.name", "string", true)])`.
+    pub fn new(entries: &[(&str, &str, bool)]) -> Schema {
+        Schema {
+            entries: entries.iter()
+                .map(|&(path, kind, required)| (path.to_string(), kind.to_string(), required))
+                .collect(),
+        }
+    }
+
+    /// Returns the values of the given table that don't match this schema, in the order
+    /// of the schema.
+    pub fn validate(&self, table: &TableData) -> Vec<SchemaError> {
+        let mut errors = Vec::new();
+        for &(ref path, ref expected, required) in &self.entries {
+            match table.get_path(path.split('.')) {
+                None if required => errors.push(SchemaError::Missing(path.clone())),
+                Some(value) if value.type_name() != expected => {
+                    errors.push(SchemaError::WrongType {
+                        path: path.clone(),
+                        expected: expected.clone(),
+                        found: value.type_name(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        errors
+    }
+}
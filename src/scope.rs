@@ -44,6 +44,22 @@ impl<'a> Scope<'a> {
         self.ordering.push(ScopeItem::Part(new_index));
     }
 
+    /// Removes the whitespace inside the scope, so that it is written as `[a.b.c]`.
+    pub fn normalize(&mut self) {
+        self.ordering.clear();
+        for index in 0..self.keys.len() {
+            if index != 0 {
+                self.ordering.push(ScopeItem::Dot);
+            }
+            self.ordering.push(ScopeItem::Part(index));
+        }
+    }
+
+    /// Returns whether the scope contains whitespace.
+    pub fn has_spacing(&self) -> bool {
+        self.ordering.iter().any(|item| if let ScopeItem::Space(_) = *item { true } else { false })
+    }
+
     /// Returns a reference to the path this scope describes.
     pub fn path(&self) -> &Vec<Key<'a>> {
         &self.keys
@@ -72,6 +88,9 @@ impl<'a> FromIterator<Key<'a>> for Scope<'a> {
     {
         let mut scope = Scope::new();
         for key in iter {
+            if !scope.keys.is_empty() {
+                scope.push_dot();
+            }
             scope.push_key(key.clone());
         }
         scope
@@ -84,6 +103,9 @@ impl<'a: 'b, 'b> FromIterator<&'b Key<'a>> for Scope<'a> {
     {
         let mut scope = Scope::new();
         for key in iter {
+            if !scope.keys.is_empty() {
+                scope.push_dot();
+            }
             scope.push_key((*key).clone());
         }
         scope
@@ -1,22 +1,40 @@
-use tabledata::TableData;
+use tabledata::{TableData, DocumentedEntries, Entry};
 use document::DocumentItem;
 use value::Value;
+use array::ArrayData;
 use key::Key;
 use std::collections::hash_map;
+use std::borrow::Cow;
 
 /// A TOML table. This is a map from strings to a TOML values.
 pub struct Table<'src: 'doc, 'doc> {
     data: &'doc mut TableData<'src>,
     order: &'doc mut Vec<DocumentItem<'src>>,
+    /// Whether this is the top-level table of the document, which has no header.
+    root: bool,
+    /// The flag of the document that tells whether it was modified.
+    modified: &'doc mut bool,
 }
 impl<'src, 'doc> Table<'src, 'doc> {
     /// Returns the value for the given key, optionally inserting a value
     /// using the provided function if the entry is empty.
+    /// Inserted values are written as entries like with `insert_smart`, while regular
+    /// tables and arrays of tables are written under headers of their own when they
+    /// have entries.
     pub fn get_or_insert_with<F: FnOnce() -> Value<'src>>(&mut self,
                                                         key: Key<'src>,
                                                         default: F)
                                                         -> &mut Value<'src> {
-        self.data.items.entry(key).or_insert_with(default)
+        if !self.data.items.contains_key(&key) {
+            *self.modified = true;
+            let value = default();
+            if value.is_noninline_array_of_tables() {
+                self.data.items.insert(key, value);
+            } else {
+                self.insert_smart(key, value);
+            }
+        }
+        self.data.items.get_mut(&key).expect("The value was inserted")
     }
 
     /// Inserts the given key as an entry to the table with the given spacing.
@@ -30,6 +48,7 @@ impl<'src, 'doc> Table<'src, 'doc> {
                                          {
         
         // TODO: validate spacing
+        *self.modified = true;
         self.data.insert_spaced(key, value, before_eq, after_eq)
     }
     
@@ -38,6 +57,7 @@ impl<'src, 'doc> Table<'src, 'doc> {
         where K: Into<Key<'src>>,
               V: Into<Value<'src>>
     {
+        *self.modified = true;
         self.data.insert_spaced(key, value, Some(" "), Some(" "))
     }
     
@@ -47,18 +67,111 @@ impl<'src, 'doc> Table<'src, 'doc> {
         where K: Into<Key<'src>>,
               V: Into<Value<'src>>
     {
-        self.data.insert(key, value)
+        *self.modified = true;
+        let value = value.into();
+        if self.root && self.data.order.is_empty() && !value.is_noninline_table() {
+            // There's no header line to end before the first entry
+            self.data.insert_spaced(key, value, Some(" "), Some(" "));
+            self.data.push_newline(false);
+        } else {
+            self.data.insert(key, value)
+        }
     }
     
+    /// Inserts a new item where its key sorts alphabetically among the keys of the
+    /// entries of the table, with the indentation of the entry it is placed before.
+    /// Note: This function attempts to be smart with the formatting.
+    pub fn insert_sorted<K, V>(&mut self, key: K, value: V)
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        if self.data.order.is_empty() {
+            self.insert_smart(key, value);
+        } else {
+            *self.modified = true;
+            self.data.insert_sorted(key, value);
+        }
+    }
+
     /// Returns a reference to the value at the given key in this table, if present.
     pub fn get<K: Into<Key<'src>>>(&self, key: K) -> Option<&Value<'src>> {
         self.data.get(key)
     }
 
     /// Returns a mutable reference to the value at the given key in this table, if
-    /// present.
+    /// present. The document counts as modified if a value is returned.
     pub fn get_mut<K: Into<Key<'src>>>(&mut self, key: K) -> Option<&mut Value<'src>> {
-        self.data.get_mut(key)
+        let value = self.data.get_mut(key);
+        *self.modified |= value.is_some();
+        value
+    }
+
+    /// Returns the entry for the given key in this table, along with its spacing and the
+    /// comment after it, if it is written as an entry.
+    pub fn get_entry<'a, K: Into<Key<'src>>>(&'a self, key: K) -> Option<Entry<'a, 'src>> {
+        self.data.get_entry(key)
+    }
+
+    /// Replaces the value of the entry for the given key, keeping its spacing and
+    /// comment. Returns the old value, or `None` if there is no entry for the key.
+    pub fn set_entry<K, V>(&mut self, key: K, value: V) -> Option<Value<'src>>
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        let old = self.data.set_entry(key, value);
+        *self.modified |= old.is_some();
+        old
+    }
+
+    /// Returns the string value at the given key in this table, if present and valid.
+    pub fn get_str<K: Into<Key<'src>>>(&self, key: K) -> Option<Cow<'src, str>> {
+        self.get(key).and_then(Value::string)
+    }
+
+    /// Returns the boolean value at the given key in this table, if present and valid.
+    pub fn get_bool<K: Into<Key<'src>>>(&self, key: K) -> Option<bool> {
+        self.get(key).and_then(Value::bool)
+    }
+
+    /// Returns the integer value at the given key in this table, if present and valid.
+    pub fn get_int<K: Into<Key<'src>>>(&self, key: K) -> Option<i64> {
+        self.get(key).and_then(Value::int)
+    }
+
+    /// Returns the float value at the given key in this table, if present and valid.
+    pub fn get_float<K: Into<Key<'src>>>(&self, key: K) -> Option<f64> {
+        self.get(key).and_then(Value::float)
+    }
+
+    /// Returns the value at the given key path, descending through tables.
+    pub fn get_path<I, V>(&self, path: I) -> Option<&Value<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        self.data.get_path(path)
+    }
+
+    /// Returns a mutable reference to the value at the given key path, descending
+    /// through tables. The document counts as modified if a value is returned.
+    pub fn get_path_mut<I, V>(&mut self, path: I) -> Option<&mut Value<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        let value = self.data.get_path_mut(path);
+        *self.modified |= value.is_some();
+        value
+    }
+
+    /// Returns a mutable reference to the array at the given key path, descending
+    /// through tables. Returns `None` if the path isn't found, or the value at it isn't
+    /// an array. The document counts as modified if an array is returned.
+    pub fn get_array_mut<I, V>(&mut self, path: I) -> Option<&mut ArrayData<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        let array = self.data.get_array_mut(path);
+        *self.modified |= array.is_some();
+        array
     }
 
     /// Returns whether the given key exists in the table.
@@ -71,39 +184,93 @@ impl<'src, 'doc> Table<'src, 'doc> {
         self.data.is_empty()
     }
 
-    /// Removes an item from this table if present.
+    /// Removes an item from this table if present, along with the line of its entry.
     pub fn remove(&mut self, key: &Key<'src>) -> Option<Value<'src>> {
-        self.data.remove(key)
+        let value = self.data.remove(key);
+        *self.modified |= value.is_some();
+        value
     }
     
+    /// Sorts the entries of the table alphabetically by their keys.
+    /// Each entry keeps its spacing, its comment on the same line, and the comment
+    /// lines directly above it.
+    pub fn sort_keys(&mut self) {
+        *self.modified = true;
+        self.data.sort_keys()
+    }
+
+    /// Removes every entry from this table, along with its comments and whitespace.
+    /// The rest of the header line of the table is kept.
+    pub fn clear(&mut self) {
+        *self.modified = true;
+        self.data.clear()
+    }
+
     /// Iterates over the keys and values in the table.
     pub fn iter(&self) -> hash_map::Iter<Key<'src>, Value<'src>> {
         self.data.iter()
     }
 
+    /// Iterates over the entries of the table in the order they are written, along
+    /// with the comment on the line above each entry, or after it on the same line.
+    pub fn documented_entries<'a>(&'a self) -> DocumentedEntries<'a, 'src> {
+        self.data.documented_entries()
+    }
+
+    /// Returns the whitespace and newlines between the entry of `a` and the entry of
+    /// `b`, which must directly follow it. See `TableData::spacing_between`.
+    pub fn spacing_between<K: Into<Key<'src>>>(&self, a: K, b: K) -> Option<String> {
+        self.data.spacing_between(a, b)
+    }
+
     /// Iterates mutably over the keys and values in the table.
+    /// The document counts as modified afterwards.
     pub fn iter_mut(&mut self) -> hash_map::IterMut<Key<'src>, Value<'src>> {
+        *self.modified = true;
         self.data.iter_mut()
     }
 }
 
 pub trait TablePrivate<'src, 'doc> {
-    /// Creates a new table wrapper.
+    /// Creates a new table wrapper, which sets the given flag when the table is modified.
     fn new(data: &'doc mut TableData<'src>,
-               order: &'doc mut Vec<DocumentItem<'src>>)
+               order: &'doc mut Vec<DocumentItem<'src>>,
+               modified: &'doc mut bool)
                -> Table<'src, 'doc>;
+
+    /// Creates a new wrapper for the top-level table of a document.
+    fn new_root(data: &'doc mut TableData<'src>,
+                order: &'doc mut Vec<DocumentItem<'src>>,
+                modified: &'doc mut bool)
+                -> Table<'src, 'doc>;
     
     /// Returns a reference to the internal data of this wrapper.
+    /// Changes made through it don't mark the document as modified.
     fn data(&mut self) -> &mut TableData<'src>;
 }
 
 impl<'src, 'doc> TablePrivate<'src, 'doc> for Table<'src, 'doc> {
     fn new(data: &'doc mut TableData<'src>,
-           order: &'doc mut Vec<DocumentItem<'src>>)
+           order: &'doc mut Vec<DocumentItem<'src>>,
+           modified: &'doc mut bool)
            -> Table<'src, 'doc> {
         Table {
             data: data,
             order: order,
+            root: false,
+            modified: modified,
+        }
+    }
+
+    fn new_root(data: &'doc mut TableData<'src>,
+                order: &'doc mut Vec<DocumentItem<'src>>,
+                modified: &'doc mut bool)
+                -> Table<'src, 'doc> {
+        Table {
+            data: data,
+            order: order,
+            root: true,
+            modified: modified,
         }
     }
     
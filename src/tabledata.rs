@@ -1,8 +1,11 @@
 
 use key::Key;
-use value::Value;
+use value::{Value, TomlData};
+use array::ArrayData;
 use scope::Scope;
-use std::collections::{HashMap, hash_map};
+use document::PathPart;
+use std::collections::{HashMap, BTreeMap, hash_map};
+use std::ops::Range;
 
 /// A format item for a TOML table.
 #[derive(Debug)]
@@ -14,11 +17,29 @@ pub enum TableItem<'src> {
         key: Key<'src>,
         before_eq: &'src str,
         after_eq: &'src str,
+        /// The byte index of the key, if the entry was read from a document.
+        pos: Option<usize>,
     },
     /// For inline tables
     Comma,
 }
 impl<'src> TableItem<'src> {
+    fn is_entry(&self) -> bool {
+        if let &TableItem::Entry { .. } = self {
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_space(&self) -> bool {
+        if let &TableItem::Space(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+
     fn is_newline(&self) -> bool {
         if let &TableItem::Newline(_) = self {
             true
@@ -28,6 +49,94 @@ impl<'src> TableItem<'src> {
     }
 }
 
+/// An iterator over the entries of a table, along with their comments.
+pub struct DocumentedEntries<'a, 'src: 'a> {
+    table: &'a TableData<'src>,
+    index: usize,
+}
+
+impl<'a, 'src> DocumentedEntries<'a, 'src> {
+    /// Returns the comment on the line above the entry at the given index of the
+    /// format order, if that line holds nothing else.
+    fn comment_above(&self, index: usize) -> Option<&'src str> {
+        let order = &self.table.order;
+        let indent = order[..index].iter().rev().take_while(|item| item.is_space()).count();
+        let end = match (index - indent).checked_sub(1) {
+            Some(end) if order[end].is_newline() => end,
+            _ => return None,
+        };
+        let start = order[..end].iter().rposition(TableItem::is_newline).map_or(0, |i| i + 1);
+        let line = &order[start..end];
+        // The first line of a table might be the rest of its header line, so only
+        // unindented comments are counted there.
+        if !is_comment_line(line) || (start == 0 && line[0].is_space()) {
+            return None;
+        }
+        line.iter()
+            .filter_map(|item| match *item {
+                TableItem::Comment(text) => Some(text),
+                _ => None,
+            })
+            .next()
+    }
+
+}
+
+/// Returns the comment after the entry at the given index of the format order, if it is
+/// on the same line.
+fn comment_after<'src>(order: &[TableItem<'src>], index: usize) -> Option<&'src str> {
+    use self::TableItem::*;
+    for item in &order[index + 1..] {
+        match *item {
+            Space(_) => {}
+            Comment(text) => return Some(text),
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Returns whether the given items of a line of a table hold a comment and no entries.
+fn is_comment_line(line: &[TableItem]) -> bool {
+    line.iter().any(|item| if let TableItem::Comment(_) = *item { true } else { false }) &&
+    line.iter().all(|item| !item.is_entry())
+}
+
+impl<'a, 'src> Iterator for DocumentedEntries<'a, 'src> {
+    type Item = (&'a Key<'src>, &'a Value<'src>, Option<&'src str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = &self.table.order;
+        while self.index < order.len() {
+            let index = self.index;
+            self.index += 1;
+            if let TableItem::Entry { ref key, .. } = order[index] {
+                let value = match self.table.items.get(key) {
+                    Some(value) => value,
+                    // The entry was removed
+                    None => continue,
+                };
+                let comment = self.comment_above(index).or_else(|| comment_after(order, index));
+                return Some((key, value, comment));
+            }
+        }
+        None
+    }
+}
+
+/// A view of an entry of a table, along with its formatting.
+#[derive(Debug)]
+pub struct Entry<'a, 'src: 'a> {
+    /// The value of the entry.
+    pub value: &'a Value<'src>,
+    /// The whitespace between the key and the equals sign.
+    pub before_eq: &'src str,
+    /// The whitespace between the equals sign and the value.
+    pub after_eq: &'src str,
+    /// The comment after the entry on the same line, without the leading `#`.
+    pub comment: Option<&'src str>,
+}
+
 /// An error found when creating a new table from a given key path.
 #[derive(Debug)]
 pub enum CreatePathError {
@@ -43,8 +152,11 @@ pub enum CreatePathError {
 /// A TOML table.
 #[derive(Debug)]
 pub struct TableData<'src> {
+    /// Whether this is an inline table.
     pub inline: bool,
+    /// The format order of the table.
     pub order: Vec<TableItem<'src>>,
+    /// The entries of the table.
     pub items: HashMap<Key<'src>, Value<'src>>,
 }
 
@@ -100,14 +212,34 @@ impl<'src> TableData<'src> {
                                          where K: Into<Key<'src>>,
                                                V: Into<Value<'src>>
                                          {
-        let key = key.into();
+        self.insert_entry(key.into(), value.into(), before_eq, after_eq, None);
+    }
+
+    /// Inserts an entry read from a document, whose key starts at the given byte index.
+    pub fn insert_read(&mut self,
+                       key: Key<'src>,
+                       value: Value<'src>,
+                       before_eq: Option<&'src str>,
+                       after_eq: Option<&'src str>,
+                       pos: usize) {
+        self.insert_entry(key, value, before_eq, after_eq, Some(pos));
+    }
+
+    /// Inserts the given key as an entry to the table with the given space.
+    fn insert_entry(&mut self,
+                    key: Key<'src>,
+                    value: Value<'src>,
+                    before_eq: Option<&'src str>,
+                    after_eq: Option<&'src str>,
+                    pos: Option<usize>) {
         let entry = TableItem::Entry {
             key: key,
             before_eq: before_eq.unwrap_or(""),
             after_eq: after_eq.unwrap_or(""),
+            pos: pos,
         };
         self.order.push(entry);
-        self.items.insert(key, value.into());
+        self.items.insert(key, value);
     }
 
     /// Attempts to find a value at the given path in the table.
@@ -174,6 +306,89 @@ impl<'src> TableData<'src> {
         self.items.get_mut(&key.into())
     }
 
+    /// Returns the value at the given key path, descending through tables.
+    /// The keys are compared by their normalized text, so `["a", "b c"]` finds
+    /// `a."b c"`.
+    pub fn get_path<I, V>(&self, path: I) -> Option<&Value<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let (last, rest) = path.split_last()?;
+        let mut table = self;
+        for key in rest {
+            table = match table.items.get(key) {
+                Some(&Value::Table(ref table)) => table,
+                _ => return None,
+            };
+        }
+        table.items.get(last)
+    }
+
+    /// Returns a mutable reference to the value at the given key path, descending
+    /// through tables.
+    pub fn get_path_mut<I, V>(&mut self, path: I) -> Option<&mut Value<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        let path = path.into_iter().map(|v| v.into()).collect::<Vec<_>>();
+        let (last, rest) = path.split_last()?;
+        let mut table = self;
+        for key in rest {
+            let current = table;
+            table = match current.items.get_mut(key) {
+                Some(&mut Value::Table(ref mut table)) => table,
+                _ => return None,
+            };
+        }
+        table.items.get_mut(last)
+    }
+
+    /// Returns a mutable reference to the array at the given key path, descending
+    /// through tables. Returns `None` if the path isn't found, or the value at it isn't
+    /// an array.
+    pub fn get_array_mut<I, V>(&mut self, path: I) -> Option<&mut ArrayData<'src>>
+        where I: IntoIterator<Item = V>,
+              V: Into<Key<'src>>
+    {
+        self.get_path_mut(path).and_then(Value::array_mut)
+    }
+
+    /// Returns the entry for the given key in this table, along with its spacing and
+    /// the comment after it, if it is written as an entry.
+    pub fn get_entry<'a, K: Into<Key<'src>>>(&'a self, key: K) -> Option<Entry<'a, 'src>> {
+        let wanted = key.into();
+        let value = self.items.get(&wanted)?;
+        for (index, item) in self.order.iter().enumerate() {
+            if let TableItem::Entry { ref key, before_eq, after_eq, .. } = *item {
+                if *key == wanted {
+                    return Some(Entry {
+                        value: value,
+                        before_eq: before_eq,
+                        after_eq: after_eq,
+                        comment: comment_after(&self.order, index),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Replaces the value of the entry for the given key, keeping its spacing and
+    /// comment. Returns the old value, or `None` if there is no entry for the key, in
+    /// which case nothing is inserted.
+    pub fn set_entry<K, V>(&mut self, key: K, value: V) -> Option<Value<'src>>
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        let key = key.into();
+        if self.get_entry(key).is_some() {
+            self.items.insert(key, value.into())
+        } else {
+            None
+        }
+    }
+
     /// Returns whether the given key exists in the table.
     pub fn contains_key<K: Into<Key<'src>>>(&self, key: K) -> bool {
         self.items.contains_key(&key.into())
@@ -184,9 +399,106 @@ impl<'src> TableData<'src> {
         self.items.is_empty()
     }
 
-    /// Removes an item from this table if present.
+    /// Removes an item from this table if present, along with the line of its entry,
+    /// or its separating comma in inline tables.
     pub fn remove(&mut self, key: &Key<'src>) -> Option<Value<'src>> {
-        self.items.remove(key)
+        use self::TableItem::*;
+        let value = self.items.remove(key)?;
+        let index = match self.order.iter().position(|item| match *item {
+            Entry { key: ref other, .. } => other == key,
+            _ => false,
+        }) {
+            Some(index) => index,
+            None => return Some(value),
+        };
+        let mut start = index;
+        let mut end = index + 1;
+        if self.inline {
+            let next = end + self.order[end..].iter().take_while(|item| item.is_space()).count();
+            let previous =
+                self.order[..start].iter().rev().take_while(|item| item.is_space()).count();
+            if let Some(&Comma) = self.order.get(next) {
+                end = next + 1;
+                end += self.order[end..].iter().take_while(|item| item.is_space()).count();
+            } else if start > previous {
+                if let Comma = self.order[start - previous - 1] {
+                    start -= previous + 1;
+                }
+            }
+        } else {
+            if start > 0 && self.order[start - 1].is_space() &&
+               (start == 1 || self.order[start - 2].is_newline()) {
+                start -= 1;
+            }
+            while let Some(item) = self.order.get(end) {
+                match *item {
+                    Space(_) | Comment(_) => end += 1,
+                    Newline(_) => {
+                        end += 1;
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        self.order.drain(start..end);
+        Some(value)
+    }
+
+    /// Removes every entry from this table, along with its comments and whitespace.
+    /// For regular tables the rest of the first line is kept, since it belongs to the
+    /// header of the table (like `[table] # comment`).
+    pub fn clear(&mut self) {
+        self.items.clear();
+        let header_end = if self.inline {
+            0
+        } else {
+            match self.order.iter().position(TableItem::is_newline) {
+                Some(end) if !self.order[..end].iter().any(TableItem::is_entry) => end + 1,
+                _ => 0,
+            }
+        };
+        self.order.truncate(header_end);
+    }
+
+    /// Returns the number of newlines at the end of the table.
+    pub fn trailing_newlines(&self) -> usize {
+        self.order
+            .iter()
+            .rev()
+            .filter(|item| !item.is_space())
+            .take_while(|item| item.is_newline())
+            .count()
+    }
+
+    /// Replaces the entries and formatting of this table with those of the given table.
+    /// The rest of the header line and the number of blank lines at the end of this
+    /// table are kept.
+    /// Note: Only for regular tables.
+    pub fn replace_contents(&mut self, table: TableData<'src>) {
+        use self::TableItem::*;
+        let trailing_newlines = self.trailing_newlines();
+        self.clear();
+        let has_header_line = !self.order.is_empty();
+        let TableData { order, items, .. } = table;
+        // The end of the first line of the new table, if it holds no entries
+        let first_line_end = match order.iter().position(TableItem::is_newline) {
+            Some(end) if !order[..end].iter().any(TableItem::is_entry) => Some(end + 1),
+            _ => None,
+        };
+        let has_entries = order.iter().any(TableItem::is_entry);
+        match first_line_end {
+            Some(end) if has_header_line => self.order.extend(order.into_iter().skip(end)),
+            None if !has_header_line && has_entries => {
+                self.order.push(Newline("\n"));
+                self.order.extend(order);
+            }
+            _ => self.order.extend(order),
+        }
+        self.items = items;
+        if trailing_newlines > 0 && has_entries {
+            self.set_trailing_blank_lines(trailing_newlines - 1);
+        }
     }
 
     /// Returns whether the inline table has a trailing comma.
@@ -206,30 +518,57 @@ impl<'src> TableData<'src> {
     /// Returns the last indentation of a key/value pair in the table.
     pub fn last_indent(&mut self) -> &'src str {
         use self::TableItem::*;
-        let mut last_was_entry = false;
-        let mut after_newline = false;
-        let mut first_space = None;
-        for item in self.order.iter().rev() {
+        // Use the indentation of the last entry, or else that of the last indented line.
+        // The first line of a table might be the rest of its header line, so spaces
+        // there don't count.
+        let mut fallback = None;
+        for (i, item) in self.order.iter().enumerate().rev() {
+            let after_newline = i > 0 && self.order[i - 1].is_newline();
             match *item {
-                Entry { .. } => {
-                    last_was_entry = true;
-                }
-                Space(text) => {
-                    if after_newline && first_space.is_none() {
-                        first_space = Some(text);
+                Entry { .. } if i == 0 || after_newline => return "",
+                Space(text) if after_newline => {
+                    match self.order.get(i + 1) {
+                        Some(next) if next.is_entry() => return text,
+                        Some(_) if fallback.is_none() => fallback = Some(text),
+                        _ => {}
                     }
-                    if last_was_entry {
-                        return text;
-                    }
-                }
-                Comment(_) | Comma => last_was_entry = false,
-                Newline(_) => {
-                    last_was_entry = false;
-                    after_newline = true;
                 }
+                _ => {}
+            }
+        }
+        fallback.unwrap_or("")
+    }
+
+    /// Returns the whitespace and newlines between the entry of `a` and the entry of
+    /// `b`, which must directly follow it.
+    /// Comments and commas between the entries are left out, so two newlines mean that
+    /// the entries are separated by a blank line or a comment line.
+    /// Returns `None` if either key isn't in the table, or `b` doesn't follow `a`.
+    pub fn spacing_between<K: Into<Key<'src>>>(&self, a: K, b: K) -> Option<String> {
+        use self::TableItem::*;
+        let (a, b) = (&a.into(), &b.into());
+        let is_entry = |item: &TableItem<'src>, wanted: &Key<'src>| match *item {
+            Entry { ref key, .. } => key == wanted && self.items.contains_key(key),
+            _ => false,
+        };
+        let start = self.order.iter().position(|item| is_entry(item, a))?;
+        let mut spacing = String::new();
+        for item in &self.order[start + 1..] {
+            match *item {
+                Space(text) | Newline(text) => spacing.push_str(text),
+                Comment(_) | Comma => {}
+                Entry { .. } if is_entry(item, b) => return Some(spacing),
+                // Skip entries that have been removed
+                Entry { ref key, .. } if !self.items.contains_key(key) => {}
+                Entry { .. } => return None,
             }
         }
-        first_space.unwrap_or("")
+        None
+    }
+
+    /// Returns the plain data of the entries in this table, without their formatting.
+    pub fn to_map(&self) -> BTreeMap<String, TomlData> {
+        self.items.iter().map(|(key, value)| (key.to_string(), value.to_data())).collect()
     }
 
     /// Iterates over the keys and values in the table.
@@ -242,6 +581,110 @@ impl<'src> TableData<'src> {
         self.items.iter_mut()
     }
 
+    /// Iterates over the entries of the table in the order they are written, along
+    /// with the comment on the line above each entry, or after it on the same line.
+    pub fn documented_entries<'a>(&'a self) -> DocumentedEntries<'a, 'src> {
+        DocumentedEntries {
+            table: self,
+            index: 0,
+        }
+    }
+
+    /// Sorts the entries of the table alphabetically by their keys.
+    /// Each entry keeps its spacing, its comment on the same line, and the comment
+    /// lines directly above it, while blank lines and other comments stay in place.
+    pub fn sort_keys(&mut self) {
+        use self::TableItem::*;
+        if self.inline {
+            let mut entries = self.order
+                .iter()
+                .filter_map(|item| match *item {
+                    Entry { key, before_eq, after_eq, pos } => {
+                        Some((key, before_eq, after_eq, pos))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.normalized().cmp(&b.0.normalized()));
+            let mut entries = entries.into_iter();
+            for item in &mut self.order {
+                if item.is_entry() {
+                    let (key, before_eq, after_eq, pos) = entries.next().unwrap();
+                    *item = Entry {
+                        key: key,
+                        before_eq: before_eq,
+                        after_eq: after_eq,
+                        pos: pos,
+                    };
+                }
+            }
+            return;
+        }
+
+        // Split the format order into lines
+        let mut lines = vec![Vec::new()];
+        for item in self.order.drain(..) {
+            let is_newline = item.is_newline();
+            lines.last_mut().unwrap().push(item);
+            if is_newline {
+                lines.push(Vec::new());
+            }
+        }
+        let missing_newline = lines.last().unwrap().iter().any(TableItem::is_entry);
+        if missing_newline {
+            lines.last_mut().unwrap().push(Newline("\n"));
+            lines.push(Vec::new());
+        }
+
+        // Find the lines of each entry, including the comment lines above it
+        // The first line might be the rest of the header line (see `comment_above`)
+        let first_is_header = lines[0].first().map_or(false, TableItem::is_space);
+        let mut blocks = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(&Entry { key, .. }) = line.iter().find(|item| item.is_entry()) {
+                let mut start = i;
+                while start > 0 && is_comment_line(&lines[start - 1]) &&
+                      !(start == 1 && first_is_header) {
+                    start -= 1;
+                }
+                blocks.push((start, i + 1, key));
+            }
+        }
+        let mut sorted = blocks.clone();
+        sorted.sort_by(|a, b| a.2.normalized().cmp(&b.2.normalized()));
+
+        let mut lines = lines.into_iter().map(Some).collect::<Vec<_>>();
+        let mut sorted_lines = Vec::new();
+        for &(start, end, _) in &sorted {
+            let mut items = Vec::new();
+            for line in &mut lines[start..end] {
+                items.extend(line.take().unwrap());
+            }
+            sorted_lines.push(items);
+        }
+        let mut sorted_lines = sorted_lines.into_iter();
+        let mut starts = blocks.iter().map(|&(start, _, _)| start).peekable();
+        for (i, line) in lines.into_iter().enumerate() {
+            if starts.peek() == Some(&i) {
+                starts.next();
+                self.order.extend(sorted_lines.next().unwrap());
+            }
+            if let Some(line) = line {
+                self.order.extend(line);
+            }
+        }
+        if missing_newline {
+            self.order.pop();
+        }
+    }
+
+    /// Returns the index after the newline that ends the last line with an entry or a
+    /// comment in the table, if there is one.
+    fn last_line_end(&self) -> Option<usize> {
+        let last = self.order.iter().rposition(|item| !item.is_space() && !item.is_newline())?;
+        self.order[last..].iter().position(TableItem::is_newline).map(|i| last + i + 1)
+    }
+
     /// Pushes the given items before the last space in the table
     fn push_before_space(&mut self, items: Vec<TableItem<'src>>) {
         if self.order.is_empty() {
@@ -267,6 +710,32 @@ impl<'src> TableData<'src> {
         }
     }
 
+    /// Replaces the whitespace at the end of the table with a newline followed by the
+    /// given number of blank lines. The indentation of the last line is kept.
+    /// Note: Only for regular tables.
+    pub fn set_trailing_blank_lines(&mut self, count: usize) {
+        use self::TableItem::*;
+        let indent = match self.order.last() {
+            Some(&Space(text)) => Some(text),
+            _ => None,
+        };
+        let mut newline = "\n";
+        loop {
+            match self.order.last() {
+                Some(&Space(_)) => {}
+                Some(&Newline(text)) => newline = text,
+                _ => break,
+            }
+            self.order.pop();
+        }
+        for _ in 0..count + 1 {
+            self.order.push(Newline(newline));
+        }
+        if let Some(indent) = indent {
+            self.order.push(Space(indent));
+        }
+    }
+
     /// Ensures that there is a newline before the first key/value pair
     pub fn ensure_newline_after_scope(&mut self) {
         if self.inline {
@@ -278,15 +747,30 @@ impl<'src> TableData<'src> {
     }
 
     /// Inserts a new item into the table.
+    /// Regular tables aren't written as entries, but under a header of their own when
+    /// they have entries.
     /// Note: This function attempts to be smart with the formatting.
     pub fn insert<K, V>(&mut self, key: K, value: V)
         where K: Into<Key<'src>>,
               V: Into<Value<'src>>
     {
+        self.insert_line(key.into(), value.into(), false)
+    }
+
+    /// Inserts a new item into the table like `insert`, but places the line of a new
+    /// entry after the last line with an entry or a comment, so that the blank lines at
+    /// the end of the table keep separating it from the next header.
+    pub fn insert_before_blank_lines<K, V>(&mut self, key: K, value: V)
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        self.insert_line(key.into(), value.into(), true)
+    }
+
+    /// Inserts a new item into the table, optionally before its trailing blank lines.
+    fn insert_line(&mut self, key: Key<'src>, value: Value<'src>, before_blank_lines: bool) {
         use self::TableItem::*;
-        let key = key.into();
-        let value = value.into();
-        if self.items.contains_key(&key) {
+        if self.items.contains_key(&key) || value.is_noninline_table() {
             self.items.insert(key, value);
         } else {
             if !self.inline {
@@ -295,6 +779,7 @@ impl<'src> TableData<'src> {
                     key: key,
                     before_eq: " ",
                     after_eq: " ",
+                    pos: None,
                 };
                 self.items.insert(key, value);
                 let mut values = Vec::new();
@@ -304,7 +789,20 @@ impl<'src> TableData<'src> {
                 }
                 values.push(entry);
                 values.push(Newline("\n")); // TODO: cr
-                self.push_before_space(values);
+                match self.last_line_end() {
+                    Some(index) if before_blank_lines => {
+                        let rest = self.order.split_off(index);
+                        self.order.extend(values);
+                        self.order.extend(rest);
+                    }
+                    _ => self.push_before_space(values),
+                }
+            } else if self.order.iter().all(TableItem::is_space) {
+                // Replace the spacing of an empty table like `{}` or `{   }`
+                self.order.clear();
+                self.order.push(Space(" "));
+                self.insert_spaced(key, value, Some(" "), Some(" "));
+                self.order.push(Space(" "));
             } else {
                 let had_comma = self.has_trailing_comma();
                 if !self.items.is_empty() {
@@ -328,37 +826,220 @@ impl<'src> TableData<'src> {
         }
     }
 
-    /// Returns whether this table is inline.
-    pub fn is_inline(&self) -> bool {
-        self.inline
+    /// Inserts a new entry where its key sorts alphabetically among the keys of the
+    /// entries of the table, before the comment lines above the entry that follows it,
+    /// and with the same indentation. Keys that sort last are inserted like with `insert`.
+    /// Note: Only for regular tables, inline tables insert the entry at their end.
+    pub fn insert_sorted<K, V>(&mut self, key: K, value: V)
+        where K: Into<Key<'src>>,
+              V: Into<Value<'src>>
+    {
+        use self::TableItem::*;
+        let key = key.into();
+        let value = value.into();
+        if self.inline || self.items.contains_key(&key) || value.is_noninline_table() {
+            return self.insert(key, value);
+        }
+        let name = key.normalized();
+        let next = self.order.iter().position(|item| match *item {
+            Entry { key: ref other, .. } => {
+                self.items.contains_key(other) && other.normalized() > name
+            }
+            _ => false,
+        });
+        let index = match next {
+            Some(index) => index,
+            None => return self.insert(key, value),
+        };
+        let mut start = index;
+        while start > 0 && self.order[start - 1].is_space() {
+            start -= 1;
+        }
+        let indent = match self.order[start] {
+            Space(text) => Some(text),
+            _ => None,
+        };
+        // Move above the comment lines directly above the entry, except for the rest of
+        // a header line (see `DocumentedEntries::comment_above`)
+        while start > 0 && self.order[start - 1].is_newline() {
+            let line_start = self.order[..start - 1]
+                .iter()
+                .rposition(TableItem::is_newline)
+                .map_or(0, |i| i + 1);
+            let line = &self.order[line_start..start - 1];
+            if !is_comment_line(line) || (line_start == 0 && line[0].is_space()) {
+                break;
+            }
+            start = line_start;
+        }
+        let newline = match self.order[index..].iter().find(|item| item.is_newline()) {
+            Some(&Newline(text)) => text,
+            _ => "\n",
+        };
+        let mut items = Vec::new();
+        if let Some(indent) = indent {
+            items.push(Space(indent));
+        }
+        items.push(Entry {
+            key: key,
+            before_eq: " ",
+            after_eq: " ",
+            pos: None,
+        });
+        items.push(Newline(newline));
+        self.items.insert(key, value);
+        let rest = self.order.split_off(start);
+        self.order.extend(items);
+        self.order.extend(rest);
     }
 
-    /// Writes the TOML representation of this value to a string.
-    pub fn write(&self, out: &mut String) {
+    /// Sets the spacing around the equals sign of every entry in this table and the
+    /// tables inside it to a single space, like `key = value`. Returns whether any
+    /// spacing was changed.
+    pub fn normalize_equals_spacing(&mut self) -> bool {
+        let mut changed = false;
+        for item in &mut self.order {
+            if let TableItem::Entry { ref mut before_eq, ref mut after_eq, .. } = *item {
+                if *before_eq != " " || *after_eq != " " {
+                    *before_eq = " ";
+                    *after_eq = " ";
+                    changed = true;
+                }
+            }
+        }
+        for (_, value) in self.items.iter_mut() {
+            match *value {
+                Value::Table(ref mut table) => changed |= table.normalize_equals_spacing(),
+                Value::Array(ref mut array) => {
+                    for value in array.iter_mut() {
+                        if let Value::Table(ref mut table) = *value {
+                            changed |= table.normalize_equals_spacing();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Cleans up the whitespace of this table without changing its entries: empty spaces
+    /// are removed, and adjacent spaces merged when they are all spaces or all tabs.
+    /// In regular tables, the whitespace at the end of lines and before the next table
+    /// header is removed too, and runs of blank lines are shortened to at most the given
+    /// number of lines.
+    /// Returns whether the formatting was changed.
+    pub fn tidy(&mut self, max_blank_lines: usize) -> bool {
         use self::TableItem::*;
-        if self.inline {
-            out.push('{');
+        let inline = self.inline;
+        let mut order: Vec<TableItem<'src>> = Vec::with_capacity(self.order.len());
+        let mut changed = false;
+        let mut newlines = 0;
+        for item in self.order.drain(..) {
+            match item {
+                Space("") => {
+                    changed = true;
+                    continue;
+                }
+                Space(text) => {
+                    if let Some(&mut Space(ref mut last)) = order.last_mut() {
+                        if let Some(merged) = merge_spaces(last, text) {
+                            *last = merged;
+                            changed = true;
+                            continue;
+                        }
+                    }
+                }
+                Newline(_) if !inline => {
+                    while let Some(&Space(_)) = order.last() {
+                        order.pop();
+                        changed = true;
+                    }
+                    newlines += 1;
+                    if newlines > max_blank_lines + 1 {
+                        changed = true;
+                        continue;
+                    }
+                }
+                _ => newlines = 0,
+            }
+            order.push(item);
+        }
+        if !inline {
+            while let Some(&Space(_)) = order.last() {
+                order.pop();
+                changed = true;
+            }
         }
+        self.order = order;
+        changed
+    }
+
+    /// Returns whether any entry of this table is written in its format order.
+    pub fn has_written_entries(&self) -> bool {
+        self.order.iter().any(|item| match *item {
+            TableItem::Entry { ref key, .. } => self.items.contains_key(key),
+            _ => false,
+        })
+    }
+
+    /// Checks that the format order and the items of this table agree: every entry in the
+    /// order must have a value, and every value must be written as exactly one entry,
+    /// except for regular tables and arrays of tables, which are written under headers.
+    /// This is a debugging aid for code that edits the format order directly.
+    pub fn validate_invariants(&self) -> Result<(), String> {
+        let mut written = HashMap::new();
         for item in &self.order {
-            match *item {
-                Space(text) | Newline(text) => out.push_str(text),
-                Comment(text) => {
-                    out.push('#');
-                    out.push_str(text);
+            if let TableItem::Entry { ref key, .. } = *item {
+                if !self.items.contains_key(key) {
+                    return Err(format!("Entry '{}' in the order has no value", key.normalized()));
                 }
-                Entry { key, before_eq, after_eq } => {
-                    key.write(out);
-                    out.push_str(before_eq);
-                    out.push('=');
-                    out.push_str(after_eq);
-                    self.items.get(&key).unwrap().write(out);
+                if written.insert(key, ()).is_some() {
+                    return Err(format!("Entry '{}' is in the order twice", key.normalized()));
                 }
-                Comma => out.push(','), 
             }
         }
-        if self.inline {
-            out.push('}');
+        for (key, value) in &self.items {
+            let under_header = match *value {
+                Value::Table(ref table) => !table.is_inline(),
+                Value::Array(ref array) => !array.is_inline(),
+                _ => false,
+            };
+            if under_header && written.contains_key(key) {
+                return Err(format!("Entry '{}' is written both as an entry and under a header",
+                                   key.normalized()));
+            } else if !under_header && !written.contains_key(key) {
+                return Err(format!("Value '{}' has no entry in the order", key.normalized()));
+            }
         }
+        Ok(())
+    }
+
+    /// Returns the byte range of the key of the given entry in the document it was read
+    /// from, or `None` if the entry wasn't read from a document.
+    pub fn key_span<K: Into<Key<'src>>>(&self, key: K) -> Option<Range<usize>> {
+        let wanted = key.into();
+        if !self.items.contains_key(&wanted) {
+            return None;
+        }
+        self.order.iter().filter_map(|item| match *item {
+            TableItem::Entry { ref key, pos: Some(pos), .. } if *key == wanted => {
+                let mut text = String::new();
+                key.write(&mut text);
+                Some(pos..pos + text.len())
+            }
+            _ => None,
+        }).next()
+    }
+
+    /// Returns whether this table is inline.
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+
+    /// Writes the TOML representation of this value to a string.
+    pub fn write(&self, out: &mut String) {
+        write_with(self, &mut Vec::new(), &mut |_, _, _| false, out);
     }
     
     /*fn find_or_insert_with_slice<F, T>(&mut self,
@@ -368,53 +1049,52 @@ impl<'src> TableData<'src> {
         where F: FnOnce() -> T,
               T: Into<Value<'src>>
     {
-        match *path {
-            [key] => {
-                let has_entry = self.items.contains_key(&key);
-                if !has_entry {
-                    let value = default().into();
-                    if value.is_noninline_table() {
-                        self.push_table(vec![key.clone()]);
-                    } else if value.is_noninline_array_of_tables() {
-                        self.push_array_of_tables(vec![key.clone()], 0);
-                    }
-                    self.items.insert(key.clone(), value);
+        let (key, rest) = match path.split_first() {
+            Some((key, rest)) => (*key, rest),
+            None => return Err(CreatePathError::EmptyPath),
+        };
+        if rest.is_empty() {
+            let has_entry = self.items.contains_key(&key);
+            if !has_entry {
+                let value = default().into();
+                if value.is_noninline_table() {
+                    self.push_table(vec![key.clone()]);
+                } else if value.is_noninline_array_of_tables() {
+                    self.push_array_of_tables(vec![key.clone()], 0);
                 }
-                Ok(self.items.get_mut(&key).unwrap())
+                self.items.insert(key.clone(), value);
             }
-            [key, _..] => {
-                let has_entry = self.items.contains_key(&key);
-                if !has_entry {
-                    let value = Value::Table(TableData::new_regular());
-                    self.items.insert(key.clone(), value);
-                    self.push_table(vec![key.clone()]);
-                    self.items.get_mut(&key).unwrap().table_mut().unwrap().find_or_insert_with_slice(&path[1..], default)
-                } else {
-                    match *self.items.get_mut(&key).unwrap() {
-                        Value::Table(ref mut table) => {
-                            table.find_or_insert_with(&path[1..], default)
+            Ok(self.items.get_mut(&key).unwrap())
+        } else {
+            let has_entry = self.items.contains_key(&key);
+            if !has_entry {
+                let value = Value::Table(TableData::new_regular());
+                self.items.insert(key.clone(), value);
+                self.push_table(vec![key.clone()]);
+                self.items.get_mut(&key).unwrap().table_mut().unwrap()
+                    .find_or_insert_with_slice(rest, default)
+            } else {
+                match *self.items.get_mut(&key).unwrap() {
+                    Value::Table(ref mut table) => {
+                        table.find_or_insert_with(rest, default)
+                    }
+                    Value::Array(ref mut array) => {
+                        if array.is_empty() {
+                            array.push(TableData::new_regular()).unwrap();
                         }
-                        Value::Array(ref mut array) => {
-                            if array.is_empty() {
-                                array.push(TableData::new_regular()).unwrap();
-                            }
-                            if ! array.is_inline() {
-                                if let Some(&mut Value::Table(ref mut table)) = array.last() {
-                                    table.find_or_insert_with_slice(&path[1..], default)
-                                } else {
-                                    unreachable!();
-                                }
+                        if ! array.is_inline() {
+                            if let Some(&mut Value::Table(ref mut table)) = array.last() {
+                                table.find_or_insert_with_slice(rest, default)
                             } else {
-                                Err(CreatePathError::InvalidScopeTable)
+                                unreachable!();
                             }
+                        } else {
+                            Err(CreatePathError::InvalidScopeTable)
                         }
-                        _ => Err(CreatePathError::InvalidScopeTable),
                     }
+                    _ => Err(CreatePathError::InvalidScopeTable),
                 }
             }
-            [] => {
-                unreachable!();
-            }
         }
     }
     
@@ -438,6 +1118,72 @@ impl<'src> TableData<'src> {
     }*/
 }
 
+/// Writes the given table at the given path, letting the function write each entry value
+/// in its place. The function returns whether it wrote the value, and the values it
+/// didn't write are written as usual, with inline tables written the same way.
+pub fn write_with<'src, F>(table: &TableData<'src>,
+                           path: &mut Vec<PathPart>,
+                           f: &mut F,
+                           out: &mut String)
+    where F: FnMut(&[PathPart], &Value<'src>, &mut String) -> bool
+{
+    use self::TableItem::*;
+    if table.inline {
+        out.push('{');
+    }
+    for item in &table.order {
+        match *item {
+            Space(text) | Newline(text) => out.push_str(text),
+            Comment(text) => {
+                out.push('#');
+                out.push_str(text);
+            }
+            Entry { key, before_eq, after_eq, .. } => {
+                key.write(out);
+                out.push_str(before_eq);
+                out.push('=');
+                out.push_str(after_eq);
+                let value = table.items.get(&key).unwrap();
+                path.push(PathPart::Key(key.to_string()));
+                if !f(path, value, out) {
+                    match *value {
+                        Value::Table(ref table) => write_with(table, path, f, out),
+                        ref value => value.write(out),
+                    }
+                }
+                path.pop();
+            }
+            Comma => out.push(','),
+        }
+    }
+    if table.inline {
+        out.push('}');
+    }
+}
+
+/// Returns a static text with the whitespace of both given texts, if they are both only
+/// spaces or only tabs.
+fn merge_spaces(a: &str, b: &str) -> Option<&'static str> {
+    const SPACES: &'static str = "                                                                ";
+    const TABS: &'static str = "\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t";
+    let len = a.len() + b.len();
+    let only = |ch| a.chars().chain(b.chars()).all(|other| other == ch);
+    if only(' ') && len <= SPACES.len() {
+        Some(&SPACES[..len])
+    } else if only('\t') && len <= TABS.len() {
+        Some(&TABS[..len])
+    } else {
+        None
+    }
+}
+
+impl<'src> Default for TableData<'src> {
+    /// Creates an empty regular table.
+    fn default() -> TableData<'src> {
+        TableData::new_regular()
+    }
+}
+
 /*pub trait TableDataPrivate {
     fn find_or_insert_table<'src, I, P>(&mut self,
                                       path: P)
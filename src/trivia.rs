@@ -0,0 +1,109 @@
+use std::ops::Range;
+use document::PathPart;
+
+/// How a comment is attached to the entries and tables of a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attachment {
+    /// The comment is on the lines directly above an entry or table header.
+    Leading,
+    /// The comment is after an entry or table header on the same line.
+    Trailing,
+    /// The comment is separated from the entries and headers around it.
+    Standalone,
+}
+
+/// A comment of a document, along with what it is attached to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'src> {
+    /// The text of the comment, without the leading `#`.
+    pub text: &'src str,
+    /// How the comment is attached.
+    pub attachment: Attachment,
+    /// The path of the entry or table that the comment is attached to, or of the table
+    /// containing a standalone comment.
+    pub path: Vec<PathPart>,
+}
+
+impl<'src> Trivia<'src> {
+    /// Returns the byte range of the comment (including the `#`) in the given text, if
+    /// the comment was parsed from it.
+    pub fn span(&self, source: &str) -> Option<Range<usize>> {
+        let start = source.as_ptr() as usize;
+        let text = self.text.as_ptr() as usize;
+        if text <= start || text + self.text.len() > start + source.len() {
+            return None;
+        }
+        let offset = text - start;
+        Some(offset - 1..offset + self.text.len())
+    }
+}
+
+/// A formatting item of a document, in the order it is written.
+#[derive(Debug)]
+pub enum Event<'src> {
+    /// Whitespace or a comma.
+    Space,
+    Newline,
+    Comment(&'src str),
+    /// An entry with the given path.
+    Entry(Vec<PathPart>),
+    /// A table header with the given path.
+    Header(Vec<PathPart>),
+}
+
+/// Classifies the comments in the given formatting items of a document.
+pub fn classify<'src>(events: &[Event<'src>]) -> Vec<Trivia<'src>> {
+    let mut trivia = Vec::new();
+    let mut table = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        let text = match *event {
+            Event::Header(ref path) => {
+                table = path.clone();
+                continue;
+            }
+            Event::Comment(text) => text,
+            _ => continue,
+        };
+        let mut before = events[..i].iter().rev().filter(|event| match **event {
+            Event::Space => false,
+            _ => true,
+        });
+        let (attachment, path) = match before.next() {
+            Some(&Event::Entry(ref path)) |
+            Some(&Event::Header(ref path)) => (Attachment::Trailing, path.clone()),
+            _ => {
+                match following_item(&events[i + 1..]) {
+                    Some(path) => (Attachment::Leading, path.clone()),
+                    None => (Attachment::Standalone, table.clone()),
+                }
+            }
+        };
+        trivia.push(Trivia {
+            text: text,
+            attachment: attachment,
+            path: path,
+        });
+    }
+    trivia
+}
+
+/// Returns the path of the entry or header after the rest of the line of a comment and
+/// the comment lines below it, if there is no blank line between them.
+fn following_item<'a>(events: &'a [Event]) -> Option<&'a Vec<PathPart>> {
+    let mut newlines = 0;
+    for event in events {
+        match *event {
+            Event::Space => {}
+            Event::Newline => {
+                newlines += 1;
+                if newlines > 1 {
+                    return None;
+                }
+            }
+            Event::Comment(_) => newlines = 0,
+            Event::Entry(ref path) |
+            Event::Header(ref path) => return Some(path),
+        }
+    }
+    None
+}
@@ -0,0 +1,116 @@
+use value::Value;
+use std::time::Duration;
+
+/// Splits the text into numbers and the units written after them, like `1h 30m` or
+/// `1.5 GB`. Returns `None` if the text is empty, or a number is missing or invalid.
+fn split_quantities(text: &str) -> Option<Vec<(&str, &str)>> {
+    let mut quantities = Vec::new();
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number = &rest[..number_end];
+        if number.trim_matches('.').is_empty() || number.matches('.').count() > 1 {
+            return None;
+        }
+        rest = rest[number_end..].trim_start();
+        let unit_end = rest.find(|c: char| !c.is_alphabetic()).unwrap_or(rest.len());
+        quantities.push((number, &rest[..unit_end]));
+        rest = rest[unit_end..].trim_start();
+    }
+    Some(quantities)
+}
+
+/// Returns the given decimal number multiplied by the given scale, dropping any
+/// remaining fraction. Returns `None` if the result is too large.
+fn scale(number: &str, scale: u128) -> Option<u128> {
+    let mut parts = number.splitn(2, '.');
+    let whole = match parts.next() {
+        Some("") | None => 0,
+        Some(whole) => whole.parse::<u128>().ok()?,
+    };
+    let mut result = whole.checked_mul(scale)?;
+    if let Some(fraction) = parts.next() {
+        // Digits beyond the precision of the scale don't change the result
+        let digits = &fraction[..fraction.len().min(20)];
+        if !digits.is_empty() {
+            let denominator = 10u128.pow(digits.len() as u32);
+            let numerator = digits.parse::<u128>().ok()?;
+            result = result.checked_add(numerator.checked_mul(scale)? / denominator)?;
+        }
+    }
+    Some(result)
+}
+
+/// Convenience parsers for values that are commonly written as strings in configuration
+/// files. These are not TOML types, and are only available with the `units` feature.
+impl<'a> Value<'a> {
+    /// Returns the duration written in this string value, as a sequence of numbers with
+    /// the units `ns`, `us` (or `µs`), `ms`, `s`, `m`, `h` and `d`, like `"30s"`,
+    /// `"1h30m"` or `"1.5 h"`. Fractions of a nanosecond are dropped.
+    /// Returns `None` if this isn't a string, or it isn't a valid duration.
+    pub fn as_duration(&self) -> Option<Duration> {
+        const SECOND: u128 = 1_000_000_000;
+        let text = self.string()?;
+        let mut nanos: u128 = 0;
+        for (number, unit) in split_quantities(&text)? {
+            let unit = match unit {
+                "ns" => 1,
+                "us" | "µs" => 1_000,
+                "ms" => 1_000_000,
+                "s" => SECOND,
+                "m" => 60 * SECOND,
+                "h" => 60 * 60 * SECOND,
+                "d" => 24 * 60 * 60 * SECOND,
+                _ => return None,
+            };
+            nanos = nanos.checked_add(scale(number, unit)?)?;
+        }
+        let secs = nanos / SECOND;
+        if secs > u64::max_value() as u128 {
+            return None;
+        }
+        Some(Duration::new(secs as u64, (nanos % SECOND) as u32))
+    }
+
+    /// Returns the number of bytes written in this string value, as a number followed
+    /// by an optional unit, like `"512"`, `"10MB"` or `"1.5 GiB"`. The units `KB`, `MB`,
+    /// `GB`, `TB` and `PB` are powers of 1000, while `KiB`, `MiB`, `GiB`, `TiB` and
+    /// `PiB` are powers of 1024. Units are case-insensitive, and fractions of a byte are
+    /// dropped. Non-negative integer values are returned as a number of bytes.
+    /// Returns `None` for other values, or if the size doesn't fit in a `u64`.
+    pub fn as_byte_size(&self) -> Option<u64> {
+        if let Value::Int(ref int) = *self {
+            return int.as_u64();
+        }
+        let text = self.string()?;
+        let quantities = split_quantities(&text)?;
+        if quantities.len() != 1 {
+            return None;
+        }
+        let (number, unit) = quantities[0];
+        let unit: u128 = match &*unit.to_lowercase() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            "pb" => 1_000_000_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            "pib" => 1 << 50,
+            _ => return None,
+        };
+        let bytes = scale(number, unit)?;
+        if bytes > u64::max_value() as u128 {
+            None
+        } else {
+            Some(bytes as u64)
+        }
+    }
+}
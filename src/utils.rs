@@ -19,66 +19,155 @@ pub fn write_string(text: &str, literal: bool, multiline: bool, out: &mut String
     }
 }
 
-/// Escapes a user-provided string as a TOML string.
-pub fn escape_string(text: &str) -> String {
+/// Escapes a user-provided string as the content of a TOML basic string.
+/// Multiline strings keep their newlines, and get an extra leading newline if the text
+/// starts with one, since the first newline of a multiline string is ignored.
+pub fn escape_string_content(text: &str, multiline: bool) -> String {
     let mut escaped = String::new();
-    escaped.push('"');
+    if multiline && text.starts_with('\n') {
+        escaped.push('\n');
+    }
     for ch in text.chars() {
         match ch {
+            '\n' if multiline => escaped.push('\n'),
             '\n' => escaped.push_str("\\n"),
             '\t' => escaped.push_str("\\t"),
             '\r' => escaped.push_str("\\r"),
             '\\' => escaped.push_str("\\\\"),
             '"' => escaped.push_str("\\\""),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{:04X}", ch as u32)),
             other => {
                 escaped.push(other);
             }
         }
     }
-    escaped.push('"');
     escaped
 }
 
+/// Escapes a user-provided string as a TOML string.
+pub fn escape_string(text: &str) -> String {
+    format!("\"{}\"", escape_string_content(text, false))
+}
+
+/// Returns the content of a TOML literal string with the given text.
+/// Returns `None` if the text cannot be written as a literal string, that is, if it
+/// contains single quotes or control characters other than tabs. Multiline literal
+/// strings may contain newlines and single quotes, but not `'''`, or a quote at the
+/// end.
+pub fn literal_string_content(text: &str, multiline: bool) -> Option<String> {
+    let invalid = if multiline {
+        text.contains("'''") || text.ends_with('\'') ||
+        text.replace("\r\n", "\n").chars().any(|ch| ch.is_control() && ch != '\t' && ch != '\n')
+    } else {
+        text.chars().any(|ch| ch == '\'' || (ch.is_control() && ch != '\t'))
+    };
+    if invalid {
+        None
+    } else if multiline && (text.starts_with('\n') || text.starts_with("\r\n")) {
+        Some(format!("\n{}", text))
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Escapes a user-provided string as a TOML literal string.
+/// Returns `None` if the string cannot be written as a literal string, that is, if it
+/// contains single quotes or control characters other than tabs.
+pub fn escape_literal(text: &str) -> Option<String> {
+    literal_string_content(text, false).map(|content| format!("'{}'", content))
+}
+
+/// Escapes a user-provided string as a TOML string.
+/// Strings containing backslashes (like Windows paths) are written as literal strings
+/// when possible, since they are easier to read without the escape characters.
+pub fn escape_user_string(text: &str) -> String {
+    if text.contains('\\') {
+        if let Some(literal) = escape_literal(text) {
+            return literal;
+        }
+    }
+    escape_string(text)
+}
+
+/// Formats a float so that it is read back as a TOML float, in scientific notation like
+/// `6.626e-34` if requested, and otherwise with a fractional part like `3.0`.
+/// The sign of negative zero is kept, so it is written as `-0.0`, while infinities are
+/// written as `inf` and `-inf`, and not-a-number as `nan`.
+pub fn format_float(value: f64, scientific: bool) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if scientific {
+        return format!("{:e}", value);
+    }
+    let mut text = format!("{}", value);
+    if value.is_finite() && !text.contains('.') {
+        text.push_str(".0");
+    }
+    text
+}
+
+/// Groups the leading digits of a formatted number by underscores, counting from the
+/// last of them, eg. `-1234567.5` to `-1_234_567.5` with groups of 3.
+pub fn group_digits(number: &str, digits: usize) -> String {
+    let sign = if number.starts_with('-') || number.starts_with('+') { 1 } else { 0 };
+    let end = number[sign..]
+        .find(|c: char| !c.is_digit(10))
+        .map_or(number.len(), |i| i + sign);
+    let mut out = String::from(&number[..sign]);
+    for (i, c) in number[sign..end].chars().enumerate() {
+        if i > 0 && (end - sign - i) % digits == 0 {
+            out.push('_');
+        }
+        out.push(c);
+    }
+    out.push_str(&number[end..]);
+    out
+}
+
+/// Returns whether the given key must be written as a quoted string, instead of as a
+/// plain key like `some-key_2`.
+pub fn key_needs_quoting(text: &str) -> bool {
+    text.is_empty() ||
+    text.chars().any(|ch| match ch {
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => false,
+        _ => true,
+    })
+}
+
 /// Creates a TOML key from a user-supplied key.
 /// If the key is valid as a 'plain' TOML key, it is borrowed,
 /// but otherwise an escaped string will be created.
 pub fn create_key<'a>(text: &'a str) -> Cow<'a, str> {
-    let mut chars = text.chars();
-    let mut simple = true;
-    match chars.next().unwrap() {
-        'a'...'z' | 'A'...'Z' | '_' | '-' => {
-            for ch in text.chars() {
-                match ch {
-                    'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => {}
-                    _ => simple = false,
-                }
-            }
-        }
-        _ => simple = false,
-    }
-    if simple {
-        Cow::Borrowed(text)
-    } else {
+    if key_needs_quoting(text) {
         Cow::Owned(escape_string(text))
+    } else {
+        Cow::Borrowed(text)
     }
 }
 
+/// Returns the given key as a segment of a dotted key path like `a."b.c".d`. Keys that
+/// are valid plain keys are borrowed, while others are written as quoted strings.
+pub fn quote_path_segment(key: &str) -> Cow<str> {
+    create_key(key)
+}
+
+/// Joins the given keys into a dotted key path like `a."b.c".d`, quoting the keys that
+/// aren't valid plain keys, so that the path is read back as the same keys.
+pub fn join_path(keys: &[&str]) -> String {
+    keys.iter().map(|key| quote_path_segment(key)).collect::<Vec<_>>().join(".")
+}
+
 /// Parses and cleans the given TOML string.
 pub fn clean_string<'a>(text: &'a str, literal: bool, multiline: bool) -> Cow<'a, str> {
     let mut chars = text.char_indices().peekable();
     if literal {
         if multiline {
-            match chars.peek() {
-                Some(&(_, '\r')) => {
-                    chars.next();
-                    chars.next();
-                    return Cow::Owned(chars.map(|(_, c)| c).collect());
-                }
-                Some(&(_, '\n')) => {
-                    chars.next();
-                    return Cow::Owned(chars.map(|(_, c)| c).collect());
-                }
-                _ => {}
+            // Ignore first newline in multiline strings
+            if text.starts_with("\r\n") {
+                return Cow::Borrowed(&text[2..]);
+            } else if text.starts_with('\n') {
+                return Cow::Borrowed(&text[1..]);
             }
         }
         return Cow::Borrowed(text);
@@ -86,21 +175,32 @@ pub fn clean_string<'a>(text: &'a str, literal: bool, multiline: bool) -> Cow<'a
     let mut string = String::new();
     let mut escaped = false;
     let mut escaped_whitespace = false;
+    let mut start = 0;
     if multiline {
         // Ignore first newline in multiline strings
-        if let Some(&(_, '\r')) = chars.peek() {
+        if text.starts_with("\r\n") {
             chars.next();
-        }
-        if let Some(&(_, '\n')) = chars.peek() {
             chars.next();
+            start = 2;
+        } else if text.starts_with('\n') {
+            chars.next();
+            start = 1;
         }
     }
+    // Strings without escapes are the same after cleaning
+    if !text.contains('\\') {
+        return Cow::Borrowed(&text[start..]);
+    }
     while let Some((i, ch)) = chars.next() {
         if escaped {
             match ch {
                 ch if ch.is_whitespace() => {
                     escaped_whitespace = true;
                 }
+                '\\' if escaped_whitespace => {
+                    // A backslash after the trimmed whitespace starts a new escape
+                    escaped_whitespace = false;
+                }
                 ch if escaped_whitespace => {
                     string.push(ch);
                     escaped = false;
@@ -113,6 +213,10 @@ pub fn clean_string<'a>(text: &'a str, literal: bool, multiline: bool) -> Cow<'a
                     string.push('\t');
                     escaped = false;
                 }
+                'r' => {
+                    string.push('\r');
+                    escaped = false;
+                }
                 'b' => {
                     string.push('\u{0008}');
                     escaped = false;
@@ -1,8 +1,12 @@
 
 use std::borrow::{Borrow, Cow};
+use std::collections::BTreeMap;
+use std::fmt;
 use tabledata::TableData;
 use array::ArrayData;
-use utils::{write_string, escape_string, clean_string};
+use datetime::Datetime;
+use utils::{write_string, escape_user_string, escape_string_content, literal_string_content,
+            clean_string, group_digits, format_float};
 
 /// A TOML string value.
 /// "Normal\nwith escapes" 'Literal'
@@ -21,6 +25,16 @@ pub enum TomlString<'a> {
     },
     /// A user-supplied string.
     User(Cow<'a, str>),
+    /// A formatted TOML string created in code, without quotes.
+    Formatted {
+        /// The text inside the quotes.
+        text: String,
+        /// Whether this is a literal string (`'`-quoted, with no escape characters
+        /// allowed).
+        literal: bool,
+        /// Whether this is a multiline (triple-quoted) string.
+        multiline: bool,
+    },
 }
 
 pub trait TomlStringPrivate {
@@ -50,10 +64,65 @@ impl<'a> TomlString<'a> {
         match *self {
             Text { text, literal, multiline } => clean_string(text, literal, multiline),
             User(ref cow) => cow.clone(),
+            Formatted { ref text, literal, multiline } => {
+                Cow::Owned(clean_string(text, literal, multiline).into_owned())
+            }
+        }
+    }
+
+    /// Rewrites this string in the given style, as far as its content permits.
+    /// Strings with newlines from a document keep being written on one or several lines
+    /// as before, and strings that can't be written as literal strings are written as
+    /// basic strings.
+    /// Strings that are already written in the resulting style are left as they are.
+    pub fn convert_style(&mut self, style: StringStyle) {
+        let text = self.clean();
+        let current = match *self {
+            TomlString::Text { literal, multiline, .. } |
+            TomlString::Formatted { literal, multiline, .. } => Some((literal, multiline)),
+            TomlString::User(_) => None,
+        };
+        let (to_literal, to_multiline) = match style {
+            StringStyle::Basic => (false, false),
+            StringStyle::Literal => (true, false),
+            StringStyle::MultilineBasic => (false, true),
+            StringStyle::MultilineLiteral => (true, true),
+        };
+        let multiline = match current {
+            Some((_, multiline)) if text.contains('\n') => multiline,
+            _ => to_multiline,
+        };
+        let literal_content = if to_literal {
+            literal_string_content(&text, multiline)
+        } else {
+            None
+        };
+        let literal = literal_content.is_some();
+        if current == Some((literal, multiline)) {
+            return;
         }
+        let content = literal_content.unwrap_or_else(|| escape_string_content(&text, multiline));
+        *self = TomlString::Formatted {
+            text: content,
+            literal: literal,
+            multiline: multiline,
+        };
     }
 }
 
+/// The quoting of a TOML string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringStyle {
+    /// A basic string, like `"a\tb"`.
+    Basic,
+    /// A literal string, like `'C:\temp'`.
+    Literal,
+    /// A multiline basic string, like `"""a\tb"""`.
+    MultilineBasic,
+    /// A multiline literal string, like `'''C:\temp'''`.
+    MultilineLiteral,
+}
+
 /// A TOML floating point number.
 /// example: `2.34`.
 #[derive(Debug)]
@@ -63,6 +132,25 @@ pub enum Float<'a> {
     Text(&'a str),
     /// A user-inserted value.
     Value(f64),
+    /// A user-inserted value, written with the digits of its integer part grouped by
+    /// underscores.
+    Grouped {
+        /// The value.
+        value: f64,
+        /// The number of digits in each group.
+        digits: usize,
+    },
+    /// A user-inserted value, written in scientific notation like `6.626e-34`.
+    Scientific(f64),
+}
+
+/// The notation used to write a user-inserted float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// Decimal notation, like `0.5` or `3.0`.
+    Decimal,
+    /// Scientific notation, like `6.626e-34`.
+    Scientific,
 }
 
 impl<'a> Float<'a> {
@@ -70,12 +158,22 @@ impl<'a> Float<'a> {
     pub fn value(&self) -> f64 {
         use self::Float::*;
         match *self {
-            Text(text) => text.parse().expect("Unparseable TOML float"),
-            Value(value) => value,
+            Text(text) => without_underscores(text).parse().expect("Unparseable TOML float"),
+            Value(value) | Grouped { value, .. } | Scientific(value) => value,
         }
     }
 }
 
+/// Returns the text of a number without the underscores between its digits, only
+/// allocating if it has any.
+fn without_underscores(text: &str) -> Cow<str> {
+    if text.contains('_') {
+        Cow::Owned(text.replace('_', ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
 /// A TOML integer.
 /// example: `3` `32_000`.
 #[derive(Debug)]
@@ -85,19 +183,189 @@ pub enum Int<'a> {
     Text(&'a str),
     /// A user-inserted value.
     Value(i64),
+    /// A user-inserted value, written with its digits grouped by underscores.
+    Grouped {
+        /// The value.
+        value: i64,
+        /// The number of digits in each group.
+        digits: usize,
+    },
+    /// A user-inserted non-negative value, written in hexadecimal, octal or binary with
+    /// the matching prefix, like `0xFF`.
+    Radix(RadixInt),
+}
+
+/// The radix of an integer written with a prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    /// Hexadecimal, written like `0xFF`.
+    Hex,
+    /// Octal, written like `0o755`.
+    Oct,
+    /// Binary, written like `0b1010`.
+    Bin,
+}
+
+/// A non-negative integer written in a given radix with its prefix, like `0xFF`.
+/// TOML doesn't allow a sign before a prefix, so negative values can't be created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadixInt {
+    value: i64,
+    radix: Radix,
+}
+
+impl RadixInt {
+    /// Creates an integer written in the given radix.
+    /// Errors if the value is negative.
+    pub fn new(value: i64, radix: Radix) -> Result<RadixInt, NegativeRadixInt> {
+        if value < 0 {
+            Err(NegativeRadixInt(value))
+        } else {
+            Ok(RadixInt {
+                value: value,
+                radix: radix,
+            })
+        }
+    }
+
+    /// Returns the value of this integer.
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    /// Returns the radix this integer is written in.
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// Writes this integer with its prefix.
+    fn write(&self, out: &mut String) {
+        out.push_str(&match self.radix {
+            Radix::Hex => format!("0x{:X}", self.value),
+            Radix::Oct => format!("0o{:o}", self.value),
+            Radix::Bin => format!("0b{:b}", self.value),
+        });
+    }
+}
+
+/// A negative integer was given to be written with a radix prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegativeRadixInt(pub i64);
+
+impl fmt::Display for NegativeRadixInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} can't be written with a radix prefix", self.0)
+    }
+}
+
+/// Parses the text of a TOML integer, which may contain underscores, a sign and a
+/// radix prefix (`0x`, `0o` or `0b`).
+fn parse_int(text: &str) -> Option<i128> {
+    let text = without_underscores(text);
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(&b'-') => (true, &text[1..]),
+        Some(&b'+') => (false, &text[1..]),
+        _ => (false, &text[..]),
+    };
+    let (radix, digits) = if digits.starts_with("0x") {
+        (16, &digits[2..])
+    } else if digits.starts_with("0o") {
+        (8, &digits[2..])
+    } else if digits.starts_with("0b") {
+        (2, &digits[2..])
+    } else {
+        (10, digits)
+    };
+    let value = i128::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
 }
 
 impl<'a> Int<'a> {
     /// Returns the value of this number.
+    /// Parsed numbers always fit in an `i64`, but this panics if the text of a number
+    /// created in code isn't a valid integer in that range.
     pub fn value(&self) -> i64 {
+        self.as_i64().expect("Unparseable or out-of-range TOML integer")
+    }
+
+    /// Returns the value of this number as an `i64`, if it is valid and in range.
+    pub fn as_i64(&self) -> Option<i64> {
+        use self::Int::*;
+        match *self {
+            Text(text) => {
+                parse_int(text).and_then(|value| {
+                    if value >= i64::min_value() as i128 && value <= i64::max_value() as i128 {
+                        Some(value as i64)
+                    } else {
+                        None
+                    }
+                })
+            }
+            Value(value) | Grouped { value, .. } => Some(value),
+            Radix(ref int) => Some(int.value),
+        }
+    }
+
+    /// Returns the value of this number as an `i128`, so that values outside the range
+    /// of an `i64` can be read as well.
+    /// Panics if the text of the number isn't a valid integer.
+    pub fn as_i128(&self) -> i128 {
         use self::Int::*;
         match *self {
-            Text(text) => text.parse().expect("Unparseable TOML float"),
-            Value(value) => value,
+            Text(text) => parse_int(text).expect("Unparseable TOML integer"),
+            Value(value) | Grouped { value, .. } => value as i128,
+            Radix(ref int) => int.value as i128,
+        }
+    }
+
+    /// Returns the value of this number as a `u64`, if it is in range.
+    pub fn as_u64(&self) -> Option<u64> {
+        let value = self.as_i128();
+        if value >= 0 && value <= u64::max_value() as i128 {
+            Some(value as u64)
+        } else {
+            None
         }
     }
 }
 
+/// A plain TOML value without any formatting, owning its data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlData {
+    /// A string value
+    String(String),
+    /// An integer
+    Int(i64),
+    /// A floating-point number
+    Float(f64),
+    /// A boolean value
+    Bool(bool),
+    /// A datetime, given as its text
+    DateTime(String),
+    /// An array of values or tables
+    Array(Vec<TomlData>),
+    /// A table, regular or inlined
+    Table(BTreeMap<String, TomlData>),
+}
+
+/// The type of a TOML value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TomlType {
+    /// A string of any style.
+    String,
+    /// A boolean.
+    Bool,
+    /// An integer.
+    Integer,
+    /// A floating point number.
+    Float,
+    /// A datetime.
+    Datetime,
+    /// An inline table, or a table under a header.
+    Table,
+    /// An inline array, or an array of tables.
+    Array,
+}
 
 /// A value in the TOML system.
 #[derive(Debug)]
@@ -111,7 +379,7 @@ pub enum Value<'a> {
     /// A floating-point number
     Float(Float<'a>),
     /// This is not validated and just given as a string. Use at your own risk.
-    DateTime(&'a str),
+    DateTime(Cow<'a, str>),
     /// A table, regular or inlined
     Table(TableData<'a>),
     /// An array of values or tables
@@ -150,12 +418,20 @@ impl<'a> ValuePrivate<'a> for Value<'a> {
 
     /// Wraps a new datetime.
     fn new_datetime(text: &'a str) -> Value<'a> {
-        Value::DateTime(text)
+        Value::DateTime(Cow::Borrowed(text))
     }
 }
 
 impl<'a> Value<'a> {
+    /// Creates a datetime from a user-supplied string.
+    /// The text is not validated, so use `Datetime::parse` to check it first.
+    pub fn owned_datetime(text: String) -> Value<'a> {
+        Value::DateTime(Cow::Owned(text))
+    }
+
     /// Checks whether this value has the same variant as the given value.
+    /// Arrays have the same type regardless of their elements, since both TOML 0.4 and 1.0
+    /// allow the arrays in an array to hold different types, like `[[1, 2], ["a"]]`.
     pub fn is_same_type(&self, other: &Value) -> bool {
         use self::Value::*;
         match (self, other) {
@@ -215,6 +491,21 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns the text of this value as it was written in the parsed document, like
+    /// `1_000`, `6.626e-34` or `1979-05-27T07:32:00Z`. Strings give the text inside
+    /// their quotes, without decoding escapes.
+    /// Returns `None` for values that weren't read from a document, for booleans, and
+    /// for tables and arrays.
+    pub fn raw_text(&self) -> Option<&'a str> {
+        match *self {
+            Value::String(TomlString::Text { text, .. }) |
+            Value::Int(Int::Text(text)) |
+            Value::Float(Float::Text(text)) |
+            Value::DateTime(Cow::Borrowed(text)) => Some(text),
+            _ => None,
+        }
+    }
+
     /// Returns the boolean value of this item (if valid).
     pub fn bool(&self) -> Option<bool> {
         if let Value::Bool(value) = *self {
@@ -224,6 +515,25 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns the boolean value of this item, also accepting the strings `"true"`
+    /// and `"false"` in any case. Use `bool` to only accept actual booleans.
+    pub fn as_bool_lenient(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            Value::String(ref string) => {
+                let text = string.clean();
+                if text.eq_ignore_ascii_case("true") {
+                    Some(true)
+                } else if text.eq_ignore_ascii_case("false") {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
     /// Returns the integer value of this item (if valid).
     pub fn int(&self) -> Option<i64> {
         if let Value::Int(ref int) = *self {
@@ -243,14 +553,171 @@ impl<'a> Value<'a> {
     }
 
     /// Returns the datetime value of this item (if valid).
+    /// Datetimes created with `owned_datetime` aren't borrowed from the source text, so
+    /// use `datetime_text` to read those.
     pub fn datetime(&self) -> Option<&'a str> {
-        if let Value::DateTime(value) = *self {
+        if let Value::DateTime(Cow::Borrowed(value)) = *self {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the text of the datetime value of this item, whether it was parsed or
+    /// created with `owned_datetime`.
+    pub fn datetime_text(&self) -> Option<&str> {
+        if let Value::DateTime(ref value) = *self {
             Some(value)
         } else {
             None
         }
     }
 
+    /// Returns the string value of this item, or the given default if it isn't a
+    /// string.
+    pub fn str_or(&self, default: &'a str) -> Cow<'a, str> {
+        self.string().unwrap_or(Cow::Borrowed(default))
+    }
+
+    /// Returns the boolean value of this item, or the given default if it isn't a
+    /// boolean.
+    pub fn bool_or(&self, default: bool) -> bool {
+        self.bool().unwrap_or(default)
+    }
+
+    /// Returns the integer value of this item, or the given default if it isn't an
+    /// integer.
+    pub fn int_or(&self, default: i64) -> i64 {
+        self.int().unwrap_or(default)
+    }
+
+    /// Returns the float value of this item, or the given default if it isn't a float.
+    pub fn float_or(&self, default: f64) -> f64 {
+        self.float().unwrap_or(default)
+    }
+
+    /// Creates a string value written as a basic string, like `"a\tb"`.
+    pub fn basic_string(text: &str) -> Value<'a> {
+        Value::new_formatted_string(escape_string_content(text, false), false, false)
+    }
+
+    /// Creates a string value written as a literal string, like `'C:\temp'`.
+    /// Errors if the text contains single quotes or control characters other than tabs.
+    pub fn literal_string(text: &str) -> Result<Value<'a>, String> {
+        match literal_string_content(text, false) {
+            Some(content) => Ok(Value::new_formatted_string(content, true, false)),
+            None => Err(format!("{:?} can't be written as a literal string", text)),
+        }
+    }
+
+    /// Creates a string value written as a multiline basic string, keeping its newlines.
+    pub fn multiline_basic(text: &str) -> Value<'a> {
+        Value::new_formatted_string(escape_string_content(text, true), false, true)
+    }
+
+    /// Creates a string value written as a multiline literal string.
+    /// Errors if the text contains `'''`, ends with a single quote or contains control
+    /// characters other than tabs and newlines.
+    pub fn multiline_literal(text: &str) -> Result<Value<'a>, String> {
+        match literal_string_content(text, true) {
+            Some(content) => Ok(Value::new_formatted_string(content, true, true)),
+            None => Err(format!("{:?} can't be written as a multiline literal string", text)),
+        }
+    }
+
+    /// Creates a string value from the formatted content of a TOML string.
+    fn new_formatted_string(text: String, literal: bool, multiline: bool) -> Value<'a> {
+        Value::String(TomlString::Formatted {
+            text: text,
+            literal: literal,
+            multiline: multiline,
+        })
+    }
+
+    /// Creates an integer value written in hexadecimal, like `0xFF`.
+    /// Errors if the value is negative, since TOML doesn't allow a sign before a prefix.
+    pub fn int_hex(value: i64) -> Result<Value<'a>, NegativeRadixInt> {
+        RadixInt::new(value, Radix::Hex).map(|int| Value::Int(Int::Radix(int)))
+    }
+
+    /// Creates an integer value written in octal, like `0o755`.
+    /// Errors if the value is negative, since TOML doesn't allow a sign before a prefix.
+    pub fn int_oct(value: i64) -> Result<Value<'a>, NegativeRadixInt> {
+        RadixInt::new(value, Radix::Oct).map(|int| Value::Int(Int::Radix(int)))
+    }
+
+    /// Creates an integer value written in binary, like `0b1010`.
+    /// Errors if the value is negative, since TOML doesn't allow a sign before a prefix.
+    pub fn int_bin(value: i64) -> Result<Value<'a>, NegativeRadixInt> {
+        RadixInt::new(value, Radix::Bin).map(|int| Value::Int(Int::Radix(int)))
+    }
+
+    /// Creates a float value written in the given notation.
+    pub fn float_with_format(value: f64, format: FloatFormat) -> Value<'a> {
+        Value::Float(match format {
+            FloatFormat::Decimal => Float::Value(value),
+            FloatFormat::Scientific => Float::Scientific(value),
+        })
+    }
+
+    /// Returns this number written with its digits grouped by underscores, eg.
+    /// `1_000_000` for a grouping of 3 digits. Floats only have the digits of their
+    /// integer part grouped. A grouping of 0 removes any underscores instead.
+    /// Other values are returned unchanged.
+    pub fn with_digit_grouping(self, digits: usize) -> Value<'a> {
+        match self {
+            Value::Int(int) => {
+                let value = int.value();
+                Value::Int(if digits == 0 {
+                    Int::Value(value)
+                } else {
+                    Int::Grouped {
+                        value: value,
+                        digits: digits,
+                    }
+                })
+            }
+            Value::Float(float) => {
+                let value = float.value();
+                Value::Float(if digits == 0 {
+                    Float::Value(value)
+                } else {
+                    Float::Grouped {
+                        value: value,
+                        digits: digits,
+                    }
+                })
+            }
+            other => other,
+        }
+    }
+
+    /// Returns the structured datetime value of this item (if valid).
+    /// Fractional seconds beyond nanosecond precision are truncated, but are still
+    /// kept in the text of the value.
+    pub fn parsed_datetime(&self) -> Option<Datetime> {
+        self.datetime_text().and_then(Datetime::parse)
+    }
+
+    /// Rewrites this string, or every string in this table or array, in the given style
+    /// where its content permits it. See `TomlString::convert_style`.
+    pub fn convert_string_style(&mut self, style: StringStyle) {
+        match *self {
+            Value::String(ref mut string) => string.convert_style(style),
+            Value::Table(ref mut table) => {
+                for (_, value) in table.iter_mut() {
+                    value.convert_string_style(style);
+                }
+            }
+            Value::Array(ref mut array) => {
+                for value in array.iter_mut() {
+                    value.convert_string_style(style);
+                }
+            }
+            _ => {}
+        }
+    }
+
     /// Returns whether this value is a regular (non-inline) table.
     pub fn is_noninline_table(&self) -> bool {
         if let Value::Table(ref table) = *self {
@@ -260,6 +727,33 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns the type of this value.
+    pub fn toml_type(&self) -> TomlType {
+        match *self {
+            Value::String(_) => TomlType::String,
+            Value::Bool(_) => TomlType::Bool,
+            Value::Int(_) => TomlType::Integer,
+            Value::Float(_) => TomlType::Float,
+            Value::DateTime(_) => TomlType::Datetime,
+            Value::Table(_) => TomlType::Table,
+            Value::Array(_) => TomlType::Array,
+        }
+    }
+
+    /// Returns the name of the type of this value, as used by the TOML test suite:
+    /// `string`, `bool`, `integer`, `float`, `datetime`, `table` or `array`.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::DateTime(_) => "datetime",
+            Value::Table(_) => "table",
+            Value::Array(_) => "array",
+        }
+    }
+
     /// Returns whether this is a regular (non-inline) array of tables.
     pub fn is_noninline_array_of_tables(&self) -> bool {
         if let Value::Array(ref array) = *self {
@@ -269,6 +763,25 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns whether this is an array of tables, either a regular one or an inline
+    /// array containing tables.
+    pub fn is_array_of_tables(&self) -> bool {
+        match *self {
+            Value::Array(ref array) if !array.is_inline() => true,
+            Value::Array(ref array) => array.iter().next().map_or(false, Value::is_table),
+            _ => false,
+        }
+    }
+
+    /// Returns an iterator over the tables in this item, if it is an array of tables.
+    pub fn array_of_tables<'b>(&'b self) -> Option<impl Iterator<Item = &'b TableData<'a>>> {
+        if self.is_array_of_tables() {
+            self.array().map(|array| array.iter().filter_map(Value::table))
+        } else {
+            None
+        }
+    }
+
     /// Returns whether this value is a table.
     pub fn is_table(&self) -> bool {
         if let Value::Table(_) = *self {
@@ -283,7 +796,7 @@ impl<'a> Value<'a> {
     // Int(Int<'a>),
     // Float(Float<'a>),
     // This is not validated and just given as a string. Use at your own risk.
-    // DateTime(&'a str),
+    // DateTime(Cow<'a, str>),
     // Table(TableData<'a>),
     // Array(ArrayData<'a>),
 
@@ -294,19 +807,60 @@ impl<'a> Value<'a> {
             String(TomlString::Text { text, literal, multiline }) => {
                 write_string(text, literal, multiline, out);
             }
+            String(TomlString::Formatted { ref text, literal, multiline }) => {
+                write_string(text, literal, multiline, out);
+            }
             String(TomlString::User(ref text)) => {
-                out.push_str(&escape_string(text.borrow()));
+                out.push_str(&escape_user_string(text.borrow()));
             }
             Bool(b) => out.push_str(if b { "true" } else { "false" }),
-            DateTime(text) => out.push_str(text),
+            DateTime(ref text) => out.push_str(text),
             Int(self::Int::Text(text)) => out.push_str(text),
             Int(self::Int::Value(v)) => out.push_str(&format!("{}", v)),
+            Int(self::Int::Grouped { value, digits }) => {
+                out.push_str(&group_digits(&format!("{}", value), digits));
+            }
+            Int(self::Int::Radix(ref int)) => int.write(out),
             Float(self::Float::Text(text)) => out.push_str(text),
-            Float(self::Float::Value(v)) => out.push_str(&format!("{}", v)),
+            Float(self::Float::Value(v)) => out.push_str(&format_float(v, false)),
+            Float(self::Float::Grouped { value, digits }) => {
+                out.push_str(&group_digits(&format_float(value, false), digits));
+            }
+            Float(self::Float::Scientific(v)) => out.push_str(&format_float(v, true)),
             Table(ref table) => table.write(out),
             Array(ref array) => array.write(out),
         }
     }
+
+    /// Returns the plain data of this value, without its formatting.
+    pub fn to_data(&self) -> TomlData {
+        match *self {
+            Value::String(ref string) => TomlData::String(string.clean().into_owned()),
+            Value::Bool(value) => TomlData::Bool(value),
+            Value::Int(ref int) => TomlData::Int(int.value()),
+            Value::Float(ref float) => TomlData::Float(float.value()),
+            Value::DateTime(ref text) => TomlData::DateTime(text.to_string()),
+            Value::Table(ref table) => TomlData::Table(table.to_map()),
+            Value::Array(ref array) => TomlData::Array(array.iter().map(Value::to_data).collect()),
+        }
+    }
+}
+
+impl<'a> PartialEq for Value<'a> {
+    /// Compares the values semantically, ignoring their formatting.
+    fn eq(&self, other: &Value<'a>) -> bool {
+        use self::Value::*;
+        match (self, other) {
+            (&String(ref a), &String(ref b)) => a.clean() == b.clean(),
+            (&Bool(a), &Bool(b)) => a == b,
+            (&Int(ref a), &Int(ref b)) => a.value() == b.value(),
+            (&Float(ref a), &Float(ref b)) => a.value() == b.value(),
+            (&DateTime(ref a), &DateTime(ref b)) => a == b,
+            (&Table(ref a), &Table(ref b)) => a.items == b.items,
+            (&Array(ref a), &Array(ref b)) => a.items() == b.items(),
+            _ => false,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for Value<'a> {
@@ -333,6 +887,12 @@ impl<'a> From<TableData<'a>> for Value<'a> {
     }
 }
 
+impl<'a> From<bool> for Value<'a> {
+    fn from(other: bool) -> Value<'a> {
+        Value::Bool(other)
+    }
+}
+
 impl<'a> From<i64> for Value<'a> {
     fn from(other: i64) -> Value<'a> {
         Value::Int(Int::Value(other))
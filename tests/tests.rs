@@ -96,7 +96,7 @@ pub fn serialize_json(table: &Table) -> Json {
 }
 
 pub fn compare_output(toml: &str, json: &str) {
-    let table = match space_toml::parse(toml) {
+    let mut table = match space_toml::parse(toml) {
         Ok(table) => table,
         Err(e) => {
             println!("Parsing failed:");
@@ -106,7 +106,7 @@ pub fn compare_output(toml: &str, json: &str) {
         }
     };
     let json = Json::from_str(json).expect("JSON parsing failed");
-    let toml_json = serialize_json(&table);
+    let toml_json = serialize_json(&table.root());
     assert!(json == toml_json,
             "expected\n{}\ngot\n{}\n",
             json.pretty(),
@@ -312,3 +312,2768 @@ pub mod valid {
         include_str!("valid/example-bom.toml"),
         include_str!("valid/example.json"));
 }
+
+pub mod edit {
+    use space_toml::{self, Value};
+
+    #[test]
+    fn reformat_array_of_tables() {
+        let text = "[[items]]\nname = \"a\"\n[[ items ]]\nname = \"b\"\n\n\n\n[[items]]\nname = \"c\"\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert!(document.reformat_array_of_tables(&["items"]));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[[items]]\nname = \"a\"\n\n[[items]]\nname = \"b\"\n\n[[items]]\nname = \"c\"\n");
+
+        assert!(document.reformat_array_of_tables_spaced(&["items"], 0));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[[items]]\nname = \"a\"\n[[items]]\nname = \"b\"\n[[items]]\nname = \"c\"\n");
+
+        assert!(!document.reformat_array_of_tables(&["missing"]));
+    }
+
+    #[test]
+    fn user_strings_prefer_literal() {
+        let mut out = String::new();
+        Value::from("C:\\path\\to").write(&mut out);
+        assert_eq!(out, "'C:\\path\\to'");
+
+        let mut out = String::new();
+        Value::from("it's C:\\").write(&mut out);
+        assert_eq!(out, "\"it's C:\\\\\"");
+
+        let mut out = String::new();
+        Value::from("plain").write(&mut out);
+        assert_eq!(out, "\"plain\"");
+    }
+
+    #[test]
+    fn diff_documents() {
+        use space_toml::Change;
+        use space_toml::PathPart::Key;
+        let old = space_toml::parse("a = 1\nb = 'same'\n[t]\nc = 2.0\n").expect("Parsing failed");
+        let new = space_toml::parse("a = 2\nb = \"same\"\n[t]\nc = 2.0\nd = true\n")
+            .expect("Parsing failed");
+        assert_eq!(old.leaf_paths(),
+                   vec![vec![Key("a".to_string())],
+                        vec![Key("b".to_string())],
+                        vec![Key("t".to_string()), Key("c".to_string())]]);
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 2);
+        match changes[0] {
+            Change::Modified(ref path, old, new) => {
+                assert_eq!(path, &vec![Key("a".to_string())]);
+                assert_eq!(old.int(), Some(1));
+                assert_eq!(new.int(), Some(2));
+            }
+            ref other => panic!("Expected a modification, got {:?}", other),
+        }
+        match changes[1] {
+            Change::Added(ref path) => {
+                assert_eq!(path, &vec![Key("t".to_string()), Key("d".to_string())])
+            }
+            ref other => panic!("Expected an addition, got {:?}", other),
+        }
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn documented_entries() {
+        let text = "# The name\nname = \"x\"\n\n# The size\nsize = 2\n[t] # Not about a\na = 1\n  \
+                    # About b\n  b = 2 # Not used\nc = 3 # About c\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let root = document.root();
+            let entries = root.documented_entries()
+                .map(|(key, _, comment)| (key.to_string(), comment))
+                .collect::<Vec<_>>();
+            assert_eq!(entries,
+                       vec![("name".to_string(), Some(" The name")),
+                            ("size".to_string(), Some(" The size"))]);
+        }
+        let table = document.find_or_insert_table(&["t"]).expect("Table not found");
+        let entries = table.documented_entries()
+            .map(|(key, value, comment)| (key.to_string(), value.int(), comment))
+            .collect::<Vec<_>>();
+        assert_eq!(entries,
+                   vec![("a".to_string(), Some(1), None),
+                        ("b".to_string(), Some(2), Some(" About b")),
+                        ("c".to_string(), Some(3), Some(" About c"))]);
+    }
+
+    #[test]
+    fn array_of_tables_detection() {
+        let text = "values = [1, 2]\ninline = [{ a = 1 }, { a = 2 }]\nempty = []\n\
+                    [[tables]]\na = 1\n[[tables]]\na = 2\n[[tables]]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        let values = root.get("values").unwrap();
+        assert!(!values.is_array_of_tables());
+        assert!(values.array_of_tables().is_none());
+        assert!(!root.get("empty").unwrap().is_array_of_tables());
+
+        let inline = root.get("inline").unwrap();
+        assert!(inline.is_array_of_tables());
+        assert_eq!(inline.array_of_tables().unwrap().count(), 2);
+
+        let tables = root.get("tables").unwrap();
+        assert!(tables.is_array_of_tables());
+        let ints = tables.array_of_tables()
+            .unwrap()
+            .map(|table| table.get("a").and_then(Value::int))
+            .collect::<Vec<_>>();
+        assert_eq!(ints, vec![Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn sort_keys() {
+        let text = "c   =  3 # cee\n\n# About b\nb=2\na = 1\n[t] # table\nz = { y = 1, x = 2 }\n\
+                    # About w\n  w = 1";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.root().sort_keys();
+        {
+            let mut table = document.find_or_insert_table(&["t"]).unwrap();
+            table.sort_keys();
+            table.get_mut("z").and_then(Value::table_mut).unwrap().sort_keys();
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "a = 1\n\n# About b\nb=2\nc   =  3 # cee\n[t] # table\n# About w\n  w = 1\n\
+                    z = { x = 2, y = 1 }");
+    }
+
+    #[test]
+    fn datetime_fraction_precision() {
+        use space_toml::{Datetime, Date, Time, Offset};
+        let text = "nine = 1979-05-27T00:32:00.999999999-07:00\n\
+                    twelve = 1979-05-27T00:32:00.123456789999Z\n\
+                    six = 1979-05-27T00:32:00.999999Z\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+
+        let root = document.root();
+        let nine = root.get("nine").and_then(Value::parsed_datetime).unwrap();
+        assert_eq!(nine,
+                   Datetime {
+                       date: Some(Date {
+                           year: 1979,
+                           month: 5,
+                           day: 27,
+                       }),
+                       time: Some(Time {
+                           hour: 0,
+                           minute: 32,
+                           second: 0,
+                           nanosecond: 999999999,
+                       }),
+                       offset: Some(Offset::Custom { minutes: -420 }),
+                   });
+        let twelve = root.get("twelve").unwrap();
+        assert_eq!(twelve.datetime(), Some("1979-05-27T00:32:00.123456789999Z"));
+        assert_eq!(twelve.parsed_datetime().unwrap().time.unwrap().nanosecond, 123456789);
+        let six = root.get("six").and_then(Value::parsed_datetime).unwrap();
+        assert_eq!(six.time.unwrap().nanosecond, 999999000);
+    }
+
+    #[test]
+    fn indent_after_header_comment() {
+        let text = "[t] # note\n[u]\n  a = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.find_or_insert_table(&["t"]).unwrap().insert_smart("x", 1);
+        document.find_or_insert_table(&["u"]).unwrap().insert_smart("b", 2);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[t] # note\nx = 1\n[u]\n  a = 1\n  b = 2\n");
+    }
+
+    #[test]
+    fn clear_table() {
+        let text = "[t] # generated\n# old\na = 1\nb = { c = 2, d = 3 }\n[u]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut table = document.find_or_insert_table(&["t"]).unwrap();
+            {
+                let inline = table.get_mut("b").and_then(Value::table_mut).unwrap();
+                inline.clear();
+                assert!(inline.is_empty());
+                let mut out = String::new();
+                inline.write(&mut out);
+                assert_eq!(out, "{}");
+            }
+            table.clear();
+            assert!(table.is_empty());
+            table.insert_smart("x", 1);
+            table.insert_smart("y", "new");
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[t] # generated\nx = 1\ny = \"new\"\n[u]\n");
+    }
+
+    #[test]
+    fn digit_grouping() {
+        let mut document = space_toml::parse("a = 1_000\n").expect("Parsing failed");
+        {
+            let mut root = document.root();
+            assert_eq!(root.get("a").and_then(Value::int), Some(1000));
+            let a = root.get_mut("a").unwrap();
+            *a = ::std::mem::replace(a, Value::from(0)).with_digit_grouping(0);
+            root.insert_smart("b", Value::from(1000000i64).with_digit_grouping(3));
+            root.insert_smart("c", Value::from(-1234567.5).with_digit_grouping(3));
+            root.insert_smart("d", Value::from(123i64).with_digit_grouping(3));
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1000\nb = 1_000_000\nc = -1_234_567.5\nd = 123\n");
+    }
+
+    #[test]
+    fn typed_getters() {
+        let text = "name = 'app'\nport = 80\ndebug = true\nratio = 0.5\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        assert_eq!(root.get_str("name").unwrap(), "app");
+        assert_eq!(root.get_int("port"), Some(80));
+        assert_eq!(root.get_bool("debug"), Some(true));
+        assert_eq!(root.get_float("ratio"), Some(0.5));
+        assert_eq!(root.get_int("name"), None);
+        assert_eq!(root.get_str("port"), None);
+        assert_eq!(root.get_bool("missing"), None);
+
+        let port = root.get("port").unwrap();
+        assert_eq!(port.int_or(8080), 80);
+        assert_eq!(port.str_or("none"), "none");
+        assert_eq!(port.bool_or(false), false);
+        assert_eq!(port.float_or(1.5), 1.5);
+        let name = root.get("name").unwrap();
+        assert_eq!(name.str_or("none"), "app");
+        assert_eq!(name.int_or(8080), 8080);
+        let missing = root.get("missing");
+        assert_eq!(missing.map_or(8080, |value| value.int_or(8080)), 8080);
+    }
+
+    #[test]
+    fn comment_at_end_of_file() {
+        let texts = ["a = 1\n# final comment",
+                     "a = 1\n# final comment\n",
+                     "# only a comment",
+                     "a = 1\n[t]\nb = 2 # final comment",
+                     "[[t]]\n# final comment\r\n"];
+        for text in texts.iter() {
+            let document = space_toml::parse(text).expect("Parsing failed");
+            let mut out = String::new();
+            document.write(&mut out);
+            assert_eq!(&out, text);
+        }
+    }
+
+    #[test]
+    fn parse_front_matter() {
+        let text = "title = \"Post\" # the title\ntags = ['a', 'b']\n\
+                    +++\nThe [body] = isn't TOML\n";
+        let (mut document, end) = space_toml::parse_prefix(text, "+++").expect("Parsing failed");
+        assert_eq!(end, 45);
+        assert_eq!(&text[end..], "+++\nThe [body] = isn't TOML\n");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, &text[..end]);
+        assert_eq!(document.root().get("tags").and_then(Value::array).map(|tags| tags.len()),
+                   Some(2));
+
+        let text = "a = 1\r\n+++\r\nbody";
+        let (_, end) = space_toml::parse_prefix(text, "+++").expect("Parsing failed");
+        assert_eq!(&text[end..], "+++\r\nbody");
+
+        let text = "a = 1\nb = 2";
+        let (_, end) = space_toml::parse_prefix(text, "+++").expect("Parsing failed");
+        assert_eq!(end, text.len());
+
+        let text = "a = \"\"\"\n+++\n\"\"\"\n+++\nbody";
+        let (mut document, end) = space_toml::parse_prefix(text, "+++").expect("Parsing failed");
+        assert_eq!(&text[end..], "+++\nbody");
+        assert_eq!(document.root().get("a").and_then(Value::string).map(|a| a.into_owned()),
+                   Some("+++\n".to_string()));
+    }
+
+    #[test]
+    fn insert_into_new_document() {
+        let mut document = space_toml::Document::new();
+        document.root().insert_smart("a", 1);
+        document.root().insert_smart("b", 2);
+        document.find_or_insert_table(&["t"]).unwrap().insert_smart("c", 3);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1\nb = 2\n\n[t]\nc = 3\n");
+    }
+
+    #[test]
+    fn build_static_document() {
+        use space_toml::{Document, TableData, ArrayData};
+        fn build() -> Document<'static> {
+            let mut document = Document::new();
+            {
+                let mut root = document.root();
+                root.insert_smart("title", format!("Generated {}", 1));
+                root.insert_smart("created", Value::owned_datetime(format!("{}-05-27", 1979)));
+                let mut ports = ArrayData::new_inline();
+                for port in 8000..8003 {
+                    ports.push(port).unwrap();
+                }
+                root.insert_smart("ports", ports);
+                let mut owner = TableData::new_inline();
+                owner.insert("name", String::from("Tom"));
+                root.insert_smart("owner", owner);
+            }
+            document.find_or_insert_table(&["server"]).unwrap().insert_smart("enabled", true);
+            document
+        }
+        let mut document = build();
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "title = \"Generated 1\"\ncreated = 1979-05-27\nports = [8000, 8001, 8002]\n\
+                    owner = { name = \"Tom\" }\n\n[server]\nenabled = true\n");
+        let mut parsed = space_toml::parse(&out).expect("Parsing failed");
+        assert!(parsed.diff(&document).is_empty());
+        {
+            let root = document.root();
+            let created = root.get("created").unwrap();
+            assert_eq!(created.datetime(), None);
+            assert_eq!(created.datetime_text(), Some("1979-05-27"));
+        }
+        assert_eq!(parsed.root().get("created").and_then(Value::datetime), Some("1979-05-27"));
+    }
+
+    #[test]
+    fn indexed_leaf_paths() {
+        use space_toml::PathPart;
+        let text = "[[servers]]\nip = '10.0.0.1'\nports = [80, 443]\n\n\
+                    [[servers]]\nip = '10.0.0.2'\n[servers.owner]\nname = 'Tom'\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let paths = document.leaf_paths()
+            .iter()
+            .map(|path| PathPart::join(path))
+            .collect::<Vec<_>>();
+        assert_eq!(paths,
+                   vec!["servers[0].ip",
+                        "servers[0].ports",
+                        "servers[1].ip",
+                        "servers[1].owner.name"]);
+        assert_eq!(document.leaf_paths()[2],
+                   vec![PathPart::Key("servers".to_string()),
+                        PathPart::Index(1),
+                        PathPart::Key("ip".to_string())]);
+
+        let changed_text = text.replace("10.0.0.2", "10.0.0.3");
+        let changed = space_toml::parse(&changed_text).expect("Parsing failed");
+        let changes = document.diff(&changed);
+        assert_eq!(changes.len(), 1);
+        match changes[0] {
+            space_toml::Change::Modified(ref path, _, _) => assert_eq!(path[1], PathPart::Index(1)),
+            ref other => panic!("Expected a modification, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrap_long_arrays() {
+        use space_toml::ArrayData;
+        let text = "[package]\nname = 'crate'\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut table = document.find_or_insert_table(&["package"]).unwrap();
+            let mut authors = ArrayData::new_inline();
+            for author in &["Alice <alice@example.com>", "Bob <bob@example.com>"] {
+                authors.push(*author).unwrap();
+            }
+            assert_eq!(authors.single_line_width(), 54);
+            assert!(authors.wrap_if_wider(40, "    "));
+            table.insert_smart("authors", authors);
+            let mut keywords = ArrayData::new_inline();
+            keywords.push("toml").unwrap();
+            assert!(!keywords.wrap_if_wider(40, "    "));
+            table.insert_smart("keywords", keywords);
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   concat!("[package]\nname = 'crate'\nauthors = [\n",
+                           "    \"Alice <alice@example.com>\",\n",
+                           "    \"Bob <bob@example.com>\",\n",
+                           "]\nkeywords = [\"toml\"]\n"));
+        assert!(space_toml::parse(&out).is_ok());
+
+        let text = "a = [1, # one\n  2]";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.root().get_mut("a").and_then(Value::array_mut).unwrap().wrap("  ");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = [\n  1, # one\n  2,\n]");
+    }
+
+    #[test]
+    fn lenient_bools() {
+        let text = "real = false\nloose = \"True\"\nshouty = 'FALSE'\nother = 'yes'\nnumber = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        let get = |key| root.get(key).unwrap();
+        assert_eq!(get("real").as_bool_lenient(), Some(false));
+        assert_eq!(get("loose").as_bool_lenient(), Some(true));
+        assert_eq!(get("loose").bool(), None);
+        assert_eq!(get("shouty").as_bool_lenient(), Some(false));
+        assert_eq!(get("other").as_bool_lenient(), None);
+        assert_eq!(get("number").as_bool_lenient(), None);
+    }
+
+    #[test]
+    fn set_table() {
+        use space_toml::{TableData, InsertTableError};
+        let text = "[package]\nname = 'app'\n\n[build] # generated\nold = 1\n\
+                    [build.cache]\nsize = 2\n\n[[build.steps]]\nrun = 'make'\n\n\
+                    [deps]\nlog = '0.3'\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut build = TableData::new_regular();
+        build.insert("target", "x86_64");
+        build.insert("jobs", 4);
+        document.set_table(&["build"], build).unwrap();
+        let mut fresh = TableData::new_regular();
+        fresh.insert("new", true);
+        document.set_table(&["fresh"], fresh).unwrap();
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[package]\nname = 'app'\n\n[build] # generated\ntarget = \"x86_64\"\n\
+                    jobs = 4\n\n[deps]\nlog = '0.3'\n\n[fresh]\nnew = true\n");
+        assert!(space_toml::parse(&out).is_ok());
+        match document.set_table(&["package", "name"], TableData::new_regular()) {
+            Err(InsertTableError::PathItemNotTable(ref key)) => assert_eq!(key, "name"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn large_and_radix_ints() {
+        use space_toml::{Int, ErrorKind};
+        let text = "max = 9223372036854775807\nmin = -9223372036854775808\nmask = 0xDEAD_beef\n\
+                    mode = 0o755\nflags = 0b1010\nneg = -1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        let root = document.root();
+        let int = |key| match *root.get(key).unwrap() {
+            Value::Int(ref int) => int,
+            ref other => panic!("Not an integer: {:?}", other),
+        };
+        assert_eq!(int("max").value(), i64::max_value());
+        assert_eq!(int("max").as_u64(), Some(i64::max_value() as u64));
+        assert_eq!(int("min").value(), i64::min_value());
+        assert_eq!(int("mask").value(), 0xDEADBEEF);
+        assert_eq!(int("mask").as_u64(), Some(0xDEADBEEF));
+        assert_eq!(int("mode").value(), 0o755);
+        assert_eq!(int("flags").value(), 0b1010);
+        assert_eq!(int("neg").as_u64(), None);
+        assert_eq!(int("neg").as_i128(), -1);
+
+        let big = Int::Text("18_446_744_073_709_551_615");
+        assert_eq!(big.as_u64(), Some(u64::max_value()));
+        assert_eq!(big.as_i128(), u64::max_value() as i128);
+        assert_eq!(big.as_i64(), None);
+
+        // TOML integers must fit in an i64
+        for &(text, pos) in &[("a = 9223372036854775808", 4),
+                              ("a = -9223372036854775809", 4),
+                              ("a = [1, 0xFFFF_FFFF_FFFF_FFFF]", 8)] {
+            match space_toml::parse(text).map(|_| ()).map_err(|e| e.kind) {
+                Err(ErrorKind::IntegerOutOfRange { pos: found }) => assert_eq!(found, pos),
+                other => panic!("Unexpected result for {:?}: {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn normalize_header_spacing() {
+        let text = "[ a . b ] # first\nx = 1\n[[ 'c d' .\te ]]\n[a.f]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert!(document.normalize_header_spacing());
+        assert!(!document.normalize_header_spacing());
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[a.b] # first\nx = 1\n[['c d'.e]]\n[a.f]\n");
+    }
+
+    #[test]
+    fn parse_inline_table() {
+        let table = space_toml::parse_inline_table(" { x = 1, y = \"two\" }\n")
+            .expect("Parsing failed");
+        assert_eq!(table.get("x").and_then(Value::int), Some(1));
+        let mut document = space_toml::parse("[point]\n").expect("Parsing failed");
+        document.find_or_insert_table(&["point"]).unwrap().insert_smart("pos", table);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[point]\npos = { x = 1, y = \"two\" }\n");
+
+        assert!(space_toml::parse_inline_table("{ x = 1 } y").is_err());
+        assert!(space_toml::parse_inline_table("{ x = 1 }, { y = 2 }").is_err());
+        assert!(space_toml::parse_inline_table("x = 1").is_err());
+        assert!(space_toml::parse_inline_table("{ x = 1").is_err());
+        assert!(space_toml::parse_inline_table("").is_err());
+    }
+
+    #[test]
+    fn spacing_between() {
+        let text = "a = 1\nb = 2\n\n  c = 3 # Note\n# About d\nd = 4\ne = 5\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut root = document.root();
+        assert_eq!(root.spacing_between("a", "b"), Some("\n".to_string()));
+        assert_eq!(root.spacing_between("b", "c"), Some("\n\n  ".to_string()));
+        assert_eq!(root.spacing_between("c", "d"), Some(" \n\n".to_string()));
+        assert_eq!(root.spacing_between("a", "c"), None);
+        assert_eq!(root.spacing_between("b", "a"), None);
+        assert_eq!(root.spacing_between("a", "x"), None);
+        root.remove(&"d".into());
+        assert_eq!(root.spacing_between("c", "e"), Some(" \n\n".to_string()));
+    }
+
+    #[test]
+    fn pound_signs() {
+        let text = "# see issue #42\n\"key#name\" = \"a # b\"\n[\"t#1\"]\n'x' = 1 # c#d ##\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        {
+            let root = document.root();
+            let entries = root.documented_entries()
+                .map(|(key, value, comment)| (key.to_string(), value.string(), comment))
+                .collect::<Vec<_>>();
+            assert_eq!(entries,
+                       vec![("key#name".to_string(), Some("a # b".into()), Some(" see issue #42"))]);
+        }
+        let table = document.find_or_insert_table(&["t#1"]).expect("Table not found");
+        let entries = table.documented_entries()
+            .map(|(key, value, comment)| (key.to_string(), value.int(), comment))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![("x".to_string(), Some(1), Some(" c#d ##"))]);
+    }
+
+    #[test]
+    fn to_map() {
+        use space_toml::TomlData;
+        use std::collections::BTreeMap;
+        let document = space_toml::parse(include_str!("../samples/example.toml"))
+            .expect("Parsing failed");
+        let map = document.to_map();
+        assert_eq!(map["title"], TomlData::String("TOML Example".to_string()));
+        let owner = match map["owner"] {
+            TomlData::Table(ref owner) => owner,
+            ref other => panic!("Not a table: {:?}", other),
+        };
+        assert_eq!(owner["dob"], TomlData::DateTime("1979-05-27T07:32:00Z".to_string()));
+        let beta = match map["servers"] {
+            TomlData::Table(ref servers) => servers["beta"].clone(),
+            ref other => panic!("Not a table: {:?}", other),
+        };
+        let mut expected = BTreeMap::new();
+        for &(key, value) in &[("ip", "10.0.0.2"), ("dc", "eqdc10"), ("country", "中国")] {
+            expected.insert(key.to_string(), TomlData::String(value.to_string()));
+        }
+        assert_eq!(beta, TomlData::Table(expected));
+        match map["products"] {
+            TomlData::Array(ref products) => {
+                assert_eq!(products.len(), 2);
+                match products[1] {
+                    TomlData::Table(ref nail) => {
+                        assert_eq!(nail["sku"], TomlData::Int(284758393));
+                    }
+                    ref other => panic!("Not a table: {:?}", other),
+                }
+            }
+            ref other => panic!("Not an array: {:?}", other),
+        }
+        match map["clients"] {
+            TomlData::Table(ref clients) => {
+                assert_eq!(clients["data"],
+                           TomlData::Array(vec![
+                    TomlData::Array(vec![TomlData::String("gamma".to_string()),
+                                         TomlData::String("delta".to_string())]),
+                    TomlData::Array(vec![TomlData::Int(1), TomlData::Int(2)]),
+                ]));
+            }
+            ref other => panic!("Not a table: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn get_array_mut() {
+        let text = "ports = { open = [80] }\n[servers.pool]\nendpoints = [\"a\", \"b\"] # Hosts\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            {
+                let endpoints = root.get_array_mut(&["servers", "pool", "endpoints"])
+                    .expect("Array not found");
+                endpoints.push("c").expect("Could not push");
+            }
+            root.get_array_mut(&["ports", "open"])
+                .expect("Array not found")
+                .push(443)
+                .expect("Could not push");
+            assert!(root.get_array_mut(&["servers", "pool"]).is_none());
+            assert!(root.get_array_mut(&["servers", "missing", "endpoints"]).is_none());
+            assert!(root.get_array_mut(Vec::<&str>::new()).is_none());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "ports = { open = [80, 443] }\n[servers.pool]\n\
+                    endpoints = [\"a\", \"b\", \"c\"] # Hosts\n");
+    }
+
+    #[test]
+    fn ensure_trailing_newline() {
+        let cases = [("a = 1", true, "a = 1\n"),
+                     ("a = 1\n\n\n", true, "a = 1\n"),
+                     ("a = 1\n", false, "a = 1"),
+                     ("a = 1\r\n[t]\r\nb = 2 # Note", true, "a = 1\r\n[t]\r\nb = 2 # Note\r\n"),
+                     ("[t]\n\n[[a]]\nb = 2\n  \n", false, "[t]\n\n[[a]]\nb = 2"),
+                     ("[t]", true, "[t]\n"),
+                     ("# Only a comment", true, "# Only a comment\n")];
+        for &(text, yes, expected) in &cases {
+            let mut document = space_toml::parse(text).expect("Parsing failed");
+            document.ensure_trailing_newline(yes);
+            let mut out = String::new();
+            document.write(&mut out);
+            assert_eq!(out, expected);
+            // Applying the policy again changes nothing
+            document.ensure_trailing_newline(yes);
+            let mut again = String::new();
+            document.write(&mut again);
+            assert_eq!(again, expected);
+        }
+    }
+
+    #[test]
+    fn get_path() {
+        let text = "a = 1\n[\"b c\".d]\n'e' = { f = \"deep\" }\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let root = document.root();
+            assert_eq!(root.get_path(&["a"]).and_then(Value::int), Some(1));
+            assert_eq!(root.get_path(&["b c", "d", "e", "f"]).and_then(Value::string),
+                       Some("deep".into()));
+            assert!(root.get_path(&["b c", "d", "missing", "f"]).is_none());
+            assert!(root.get_path(&["a", "b"]).is_none());
+            assert!(root.get_path(Vec::<&str>::new()).is_none());
+        }
+        *document.root().get_path_mut(&["b c", "d", "e", "f"]).expect("Value not found") =
+            Value::from("changed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1\n[\"b c\".d]\n'e' = { f = \"changed\" }\n");
+    }
+
+    #[test]
+    fn example_fixture_features() {
+        let mut v3 = space_toml::parse(include_str!("valid/example-v0.3.0.toml"))
+            .expect("Parsing failed");
+        let mut v4 = space_toml::parse(include_str!("valid/example-v0.4.0.toml"))
+            .expect("Parsing failed");
+        let v3 = v3.root();
+        let v4 = v4.root();
+        // Datetimes
+        assert_eq!(v3.get_path(vec!["Datetime", "key1"]).and_then(Value::datetime),
+                   Some("1979-05-27T07:32:00Z"));
+        // Inline tables
+        assert_eq!(v4.get_path(vec!["table", "inline", "point", "y"]).and_then(Value::int),
+                   Some(2));
+        assert_eq!(v4.get_path(vec!["table", "inline", "name", "last"])
+                       .and_then(Value::string)
+                       .map(|name| name.into_owned()),
+                   Some("Preston-Werner".to_string()));
+        // Digit separators
+        assert_eq!(v4.get_path(vec!["integer", "underscores", "key2"]).and_then(Value::int),
+                   Some(5349221));
+        // Arrays of tables, with nested arrays of tables
+        assert_eq!(v4.get("products").and_then(Value::array).map(|products| products.len()),
+                   Some(3));
+        let fruit = v4.get("fruit").and_then(Value::array).expect("No fruit");
+        let varieties = fruit.iter()
+            .map(|fruit| fruit.table().and_then(|f| f.get("variety")).and_then(Value::array))
+            .map(|variety| variety.map(|variety| variety.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(varieties, vec![Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn edit_nested_table_through_value() {
+        use space_toml::TableData;
+        let text = "[a.b]\nx = 1\nold = 0\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            let a = root.get_mut("a").and_then(Value::table_mut).expect("Table not found");
+            {
+                let b = a.get_mut("b").and_then(Value::table_mut).expect("Table not found");
+                b.insert("y", 2);
+                b.remove(&"old".into());
+                b.insert("c", TableData::new_regular());
+                b.get_mut("c").and_then(Value::table_mut).expect("Table not found").insert("w", true);
+                b.insert("d", TableData::new_regular());
+            }
+            a.insert("z", 3);
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[a.b]\nx = 1\ny = 2\n\n[a]\nz = 3\n\n[a.b.c]\nw = true\n");
+        let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        assert!(document.diff(&reparsed).is_empty());
+        let text = "t = { a = 1, b = 2, c = 3 }\n  x = 1 # Gone\ny = 2\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            root.remove(&"x".into());
+            let t = root.get_mut("t").and_then(Value::table_mut).expect("Table not found");
+            t.remove(&"a".into());
+            t.remove(&"c".into());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "t = { b = 2 }\ny = 2\n");
+    }
+
+    #[test]
+    fn validate_schema() {
+        use space_toml::{Schema, SchemaError};
+        let text = "[package]\nname = \"crate\"\nversion = 1.0\n[dependencies]\nlog = \"0.3\"\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let schema = Schema::new(&[("package.name", "string", true),
+                                   ("package.version", "string", true),
+                                   ("package.authors", "array", true),
+                                   ("package.license", "string", false),
+                                   ("dependencies", "table", false),
+                                   ("dependencies.log", "string", false)]);
+        let errors = document.validate(&schema);
+        assert_eq!(errors,
+                   vec![SchemaError::WrongType {
+                            path: "package.version".to_string(),
+                            expected: "string".to_string(),
+                            found: "float",
+                        },
+                        SchemaError::Missing("package.authors".to_string())]);
+        assert_eq!(errors[0].to_string(),
+                   "Expected 'package.version' to be string, but found float");
+        assert_eq!(errors[1].to_string(), "Missing required value 'package.authors'");
+    }
+
+    #[test]
+    fn float_formats() {
+        use space_toml::FloatFormat;
+        let text = "h = 6.626e-34\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            let h = root.get_float("h").expect("Float not found");
+            let computed = h * 2.0 / 2.0;
+            let scientific = Value::float_with_format(computed, FloatFormat::Scientific);
+            root.insert_smart("scientific", scientific);
+            root.insert_smart("big", Value::float_with_format(3e10, FloatFormat::Scientific));
+            root.insert_smart("decimal", Value::float_with_format(2.0, FloatFormat::Decimal));
+            root.insert_smart("half", 0.5);
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "h = 6.626e-34\nscientific = 6.626e-34\nbig = 3e10\ndecimal = 2.0\n\
+                    half = 0.5\n");
+        let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        assert!(document.diff(&reparsed).is_empty());
+
+        let mut out = String::new();
+        Value::from(::std::f64::NAN).write(&mut out);
+        Value::float_with_format(::std::f64::NAN, FloatFormat::Scientific).write(&mut out);
+        assert_eq!(out, "nannan");
+    }
+
+    #[test]
+    fn key_needs_quoting() {
+        use space_toml::key_needs_quoting;
+        for key in &["name", "some-key_2", "_private", "A", "2nd", "1234", "-"] {
+            assert!(!key_needs_quoting(key), "{:?} needs quoting", key);
+        }
+        for key in &["", "a b", "a.b", "ключ", "a=b", "\"quoted\""] {
+            assert!(key_needs_quoting(key), "{:?} doesn't need quoting", key);
+        }
+        let mut document = space_toml::parse("").expect("Parsing failed");
+        document.root().insert_smart("", 1);
+        document.root().insert_smart("2nd", 2);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "\"\" = 1\n2nd = 2\n");
+        let mut document = space_toml::parse(&out).expect("Parsing failed");
+        assert_eq!(document.root().get("2nd").and_then(Value::int), Some(2));
+    }
+
+    #[test]
+    fn upsert_table_by() {
+        use space_toml::TableData;
+        let text = "[[users]] # Admin\nname = \"ann\"\nrole = \"admin\"\n\n\
+                    [[users]]\nname = \"bob\"\nrole = \"user\"\n\n[settings]\nx = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            let users = root.get_array_mut(&["users"]).expect("Array not found");
+            let mut ann = TableData::new_regular();
+            ann.insert("name", "ann");
+            ann.insert("role", "owner");
+            users.upsert_table_by("name", ann).expect("Could not upsert");
+            let mut cid = TableData::new_regular();
+            cid.insert("name", "cid");
+            users.upsert_table_by("name", cid).expect("Could not upsert");
+            assert_eq!(users.len(), 3);
+        }
+        {
+            let mut settings = document.find_or_insert_table(&["settings"]).expect("No table");
+            settings.insert_smart("ports", space_toml::ArrayData::new_inline());
+            let ports = settings.get_array_mut(&["ports"]).expect("Array not found");
+            ports.push(80).expect("Could not push");
+            assert!(ports.upsert_table_by("name", TableData::new_inline()).is_err());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[[users]] # Admin\nname = \"ann\"\nrole = \"owner\"\n\n\
+                    [[users]]\nname = \"bob\"\nrole = \"user\"\n\n[settings]\nx = 1\n\
+                    ports = [80]\n\n[[users]]\nname = \"cid\"\n");
+        let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        assert!(document.diff(&reparsed).is_empty());
+    }
+
+    #[test]
+    fn default_values() {
+        use space_toml::{Document, TableData, ArrayData};
+        use std::mem;
+        let mut document = Document::default();
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "");
+        let table = TableData::default();
+        assert!(!table.is_inline() && table.is_empty());
+        let mut array = ArrayData::default();
+        assert!(array.is_inline() && array.is_empty());
+        array.push(1).expect("Could not push");
+        let taken = mem::replace(&mut array, Default::default());
+        assert_eq!(taken.len(), 1);
+        assert!(array.is_empty());
+        document.root().insert_smart("a", taken);
+        out.clear();
+        document.write(&mut out);
+        assert_eq!(out, "a = [1]\n");
+    }
+
+    #[test]
+    fn for_each_table_mut() {
+        let text = "n = 0\n[a]\nn = 1\n[a.b] # Note\nn = 2\n[[c]]\nn = 3\n[[c]]\nn = 4\n[d]\nx = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut paths = Vec::new();
+        document.for_each_table_mut(|path, table| {
+            paths.push(path.iter().map(|key| key.to_string()).collect::<Vec<_>>().join("."));
+            let n = table.get("n").and_then(Value::int);
+            if let Some(n) = n {
+                table.insert("n", n + 10);
+            }
+        });
+        assert_eq!(paths, vec!["", "a", "a.b", "c", "c", "d"]);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "n = 10\n[a]\nn = 11\n[a.b] # Note\nn = 12\n[[c]]\nn = 13\n[[c]]\nn = 14\n\
+                    [d]\nx = 1\n");
+    }
+
+    #[test]
+    fn empty_inline_tables() {
+        let text = "a = {}\nb = {   }\nc = { }\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        {
+            let mut root = document.root();
+            for &key in &["a", "b", "c"] {
+                let table = root.get_mut(key).and_then(Value::table_mut).expect("No table");
+                table.insert("key", "val");
+            }
+        }
+        out.clear();
+        document.write(&mut out);
+        assert_eq!(out, "a = { key = \"val\" }\nb = { key = \"val\" }\nc = { key = \"val\" }\n");
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let mut document = space_toml::parse("").expect("Parsing failed");
+        {
+            let mut root = document.root();
+            root.insert_smart("basic", Value::basic_string("a\tb \"c\" \\ \u{1}"));
+            root.insert_smart("literal",
+                              Value::literal_string("C:\\temp").expect("Invalid literal"));
+            root.insert_smart("lines", Value::multiline_basic("\none\n\"two\"\n"));
+            root.insert_smart("raw",
+                              Value::multiline_literal("it's\n\\d+").expect("Invalid literal"));
+            assert!(Value::literal_string("it's").is_err());
+            assert!(Value::literal_string("a\nb").is_err());
+            assert!(Value::multiline_literal("a'''b").is_err());
+            assert!(Value::multiline_literal("quote'").is_err());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "basic = \"a\\tb \\\"c\\\" \\\\ \\u0001\"\nliteral = 'C:\\temp'\n\
+                    lines = \"\"\"\n\none\n\\\"two\\\"\n\"\"\"\nraw = '''it's\n\\d+'''\n");
+        let root = document.root();
+        assert_eq!(root.get_str("basic"), Some("a\tb \"c\" \\ \u{1}".into()));
+        assert_eq!(root.get_str("literal"), Some("C:\\temp".into()));
+        assert_eq!(root.get_str("lines"), Some("\none\n\"two\"\n".into()));
+        assert_eq!(root.get_str("raw"), Some("it's\n\\d+".into()));
+        let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        assert!(document.diff(&reparsed).is_empty());
+    }
+
+    #[test]
+    fn trivia() {
+        use space_toml::{Attachment, PathPart};
+        let text = "# Standalone header comment\n\n# The name\nname = \"x\" # Trailing name\n\
+                    \n# About the server\n# More about it\n[server] # Trailing server\n\
+                    port = 80\n# Alone at the end\n\n[[users]]\n  # About the user\n  id = 1\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let trivia = document.trivia();
+        let found = trivia.iter()
+            .map(|trivia| (trivia.text, trivia.attachment, PathPart::join(&trivia.path)))
+            .collect::<Vec<_>>();
+        assert_eq!(found,
+                   vec![(" Standalone header comment", Attachment::Standalone, "".to_string()),
+                        (" The name", Attachment::Leading, "name".to_string()),
+                        (" Trailing name", Attachment::Trailing, "name".to_string()),
+                        (" About the server", Attachment::Leading, "server".to_string()),
+                        (" More about it", Attachment::Leading, "server".to_string()),
+                        (" Trailing server", Attachment::Trailing, "server".to_string()),
+                        (" Alone at the end", Attachment::Standalone, "server".to_string()),
+                        (" About the user", Attachment::Leading, "users[0].id".to_string())]);
+        let span = trivia[2].span(text).expect("No span");
+        assert_eq!(&text[span], "# Trailing name");
+        assert!(trivia[2].span("name = 1").is_none());
+    }
+
+    #[test]
+    fn starts_with_header() {
+        let texts = ["[a]\nx = 1\n", "[a]", "[a]\n", "[[a]]\nx = 1", "[package]\r\nname = \"x\"\r\n"];
+        for &text in &texts {
+            let document = space_toml::parse(text).expect("Parsing failed");
+            let mut out = String::new();
+            document.write(&mut out);
+            assert_eq!(out, text);
+        }
+        let mut document = space_toml::parse("[a]\nx = 1\n").expect("Parsing failed");
+        document.find_or_insert_table(&["a"]).expect("Table not found").insert_smart("y", 2);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[a]\nx = 1\ny = 2\n");
+    }
+
+    #[test]
+    fn validate_invariants() {
+        let text = "a = 1 # One\nb = { c = [1, 2], d = {} }\n[e]\nf = 2\n[[g]]\nh = 3\n[e.i]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.for_each_table_mut(|path, table| {
+            assert_eq!(table.validate_invariants(), Ok(()), "{:?}", path);
+            table.insert("z", 26);
+            table.remove(&"a".into());
+            assert_eq!(table.validate_invariants(), Ok(()), "{:?}", path);
+        });
+
+        let mut table = space_toml::TableData::new_regular();
+        table.insert("a", 1);
+        table.insert("b", 2);
+        table.items.clear();
+        assert_eq!(table.validate_invariants(),
+                   Err(String::from("Entry 'a' in the order has no value")));
+
+        let mut table = space_toml::TableData::new_inline();
+        table.items.insert("a".into(), Value::from(1));
+        assert_eq!(table.validate_invariants(),
+                   Err(String::from("Value 'a' has no entry in the order")));
+    }
+
+    #[test]
+    fn write_with() {
+        use space_toml::PathPart;
+        let text = "name = \"app\" # The name\n\n[secrets]\ntoken   = \"abc\"  # Rotated\n\
+                    keys = { api = \"x\", n = 2 }\n\n[[users]]\npassword = \"hunter2\"\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write_with(&mut out, |path, value| {
+            let secret = path.first() == Some(&PathPart::Key(String::from("secrets"))) ||
+                         PathPart::join(path) == "users[0].password";
+            match *value {
+                Value::String(_) if secret => Some("\"***\"".into()),
+                _ => None,
+            }
+        });
+        assert_eq!(out,
+                   "name = \"app\" # The name\n\n[secrets]\ntoken   = \"***\"  # Rotated\n\
+                    keys = { api = \"***\", n = 2 }\n\n[[users]]\npassword = \"***\"\n");
+        let mut unchanged = String::new();
+        document.write(&mut unchanged);
+        assert_eq!(unchanged, text);
+    }
+
+    #[test]
+    fn signed_floats() {
+        let text = "a = +1.5\nb = -0.0\nc = +0.0\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            assert_eq!(root.get_float("a"), Some(1.5));
+            root.insert_smart("negative_zero", -0.0);
+            root.insert_smart("zero", 0.0);
+            root.insert_smart("negative", -2.0);
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "a = +1.5\nb = -0.0\nc = +0.0\nnegative_zero = -0.0\nzero = 0.0\n\
+                    negative = -2.0\n");
+        let mut reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        let zero = reparsed.root().get_float("negative_zero").expect("Float not found");
+        assert!(zero == 0.0 && zero.is_sign_negative());
+    }
+
+    #[test]
+    fn convert_string_style() {
+        use space_toml::StringStyle;
+        let text = "a = \"plain\"\nb = \"C:\\\\temp\" # Path\nc = \"it's\"\nd = 'kept'\n\
+                    e = \"\"\"multi\nline\"\"\"\nf = [\"x\", \"y\\u0001\"]\n\
+                    [t]\ng = { h = \"in\" }\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.root().insert_smart("user", "\\d+");
+        document.convert_string_style(StringStyle::Literal);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "a = 'plain'\nb = 'C:\\temp' # Path\nc = \"it's\"\nd = 'kept'\n\
+                    e = '''multi\nline'''\nf = ['x', \"y\\u0001\"]\nuser = '\\d+'\n[t]\n\
+                    g = { h = 'in' }\n");
+        let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+        assert!(document.diff(&reparsed).is_empty());
+
+        document.convert_string_style(StringStyle::Basic);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "a = \"plain\"\nb = \"C:\\\\temp\" # Path\nc = \"it's\"\nd = \"kept\"\n\
+                    e = \"\"\"multi\nline\"\"\"\nf = [\"x\", \"y\\u0001\"]\nuser = \"\\\\d+\"\n\
+                    [t]\ng = { h = \"in\" }\n");
+    }
+
+    #[test]
+    fn patch_value() {
+        use space_toml::PathPart;
+        fn path(parts: &[&str]) -> Vec<PathPart> {
+            parts.iter()
+                .map(|part| match part.parse() {
+                    Ok(index) => PathPart::Index(index),
+                    Err(_) => PathPart::Key(part.to_string()),
+                })
+                .collect()
+        }
+        let text = "title = \"x\" # Title\n[server]\nport   =  8080 # Port\n\
+                    limits = { cpu = 2, mem = \"1G\" }\n[[users]]\nname = \"a\"\n[[users]]\n\
+                    name = \"b\"\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let cases = vec![(path(&["server", "port"]), Value::from(9090), "8080"),
+                         (path(&["server", "limits", "mem"]), Value::from("2G"), "\"1G\""),
+                         (path(&["users", "1", "name"]), Value::from("c"), "\"b\""),
+                         (path(&["title"]), Value::from(true), "\"x\"")];
+        let mut patched = String::from(text);
+        for (path, value, old) in cases.into_iter() {
+            let (range, replacement) = document.patch_value(&path, value).expect("No value");
+            assert_eq!(&text[range.clone()], old);
+            let mut out = String::from(text);
+            out.replace_range(range, &replacement);
+            assert_eq!(out, text.replacen(old, &replacement, 1));
+            patched = patched.replacen(old, &replacement, 1);
+        }
+        assert_eq!(patched,
+                   "title = true # Title\n[server]\nport   =  9090 # Port\n\
+                    limits = { cpu = 2, mem = \"2G\" }\n[[users]]\nname = \"a\"\n[[users]]\n\
+                    name = \"c\"\n");
+        assert!(document.patch_value(&path(&["server", "missing"]), 1).is_none());
+        assert!(document.patch_value(&path(&["server"]), 1).is_none());
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let text = "same = [[1, 2], [3, 4]]\nmixed = [ [ 1, 2 ], [\"a\", \"b\"] ]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut root = document.root();
+            let same = root.get_array_mut(&["same"]).expect("Array not found");
+            let mut strings = space_toml::ArrayData::new_inline();
+            strings.push("x").expect("Could not push");
+            same.push(strings).expect("Could not push an array of another type");
+            assert!(same.push(5).is_err());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "same = [[1, 2], [3, 4], [\"x\"]]\nmixed = [ [ 1, 2 ], [\"a\", \"b\"] ]\n");
+        assert!(space_toml::parse("a = [[1], 2]").is_err());
+    }
+
+    #[test]
+    fn key_span() {
+        let text = "name = \"app\"\n[server]\n  \"host name\"  = \"x\"\nport = 80\n\
+                    limits = { 'max cpu' = 2 }\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let cases = [(&["name"][..], "name"),
+                     (&["server", "host name"][..], "\"host name\""),
+                     (&["server", "port"][..], "port"),
+                     (&["server", "limits", "max cpu"][..], "'max cpu'")];
+        for &(path, key) in cases.iter() {
+            let span = document.key_span(path).expect("Key not found");
+            assert_eq!(&text[span], key);
+        }
+        assert!(document.key_span(&["server"]).is_none());
+        assert!(document.key_span(&["server", "missing"]).is_none());
+        assert!(document.key_span(&[]).is_none());
+        document.root().insert_smart("added", 1);
+        assert!(document.key_span(&["added"]).is_none());
+    }
+
+    #[test]
+    fn apply_patches() {
+        let text = "# Config\nname   = \"app\"  # Name\n\n[server]\nhost = \"localhost\"\n\
+                    port = 8080\nlimits = { cpu = 2, mem = \"1G\" } # Limits\n\n\
+                    [logging]\nlevel = 'info'\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let path = |parts: &[&str]| parts.iter().map(|part| part.to_string()).collect::<Vec<_>>();
+        let patches = vec![(path(&["name"]), Value::from("service")),
+                           (path(&["server", "limits", "mem"]), Value::from("2G")),
+                           (path(&["logging", "level"]), Value::from("debug")),
+                           (path(&["server", "missing"]), Value::from(1)),
+                           (path(&["server"]), Value::from(1))];
+        let out = document.apply_patches(patches);
+        let expected = text.replace("\"app\"", "\"service\"")
+            .replace("\"1G\"", "\"2G\"")
+            .replace("'info'", "\"debug\"");
+        assert_eq!(out, expected);
+        let start = text.find("\"app\"").unwrap();
+        let end = text.find("'info'").unwrap() + "'info'".len();
+        assert_eq!(&out[..start], &text[..start]);
+        assert_eq!(&out[out.len() - (text.len() - end)..], &text[end..]);
+    }
+
+    #[test]
+    fn multiline_leading_newline() {
+        use space_toml::TomlString;
+        let text = "a = '''\r\none'''\nb = '''\ntwo'''\nc = \"\"\"\r\nthree\"\"\"\nd = '''four'''\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let root = document.root();
+            assert_eq!(root.get_str("a").as_ref().map(|s| s.as_ref()), Some("one"));
+            assert_eq!(root.get_str("b").as_ref().map(|s| s.as_ref()), Some("two"));
+            assert_eq!(root.get_str("c").as_ref().map(|s| s.as_ref()), Some("three"));
+            assert_eq!(root.get_str("d").as_ref().map(|s| s.as_ref()), Some("four"));
+        }
+        for &literal in &[true, false] {
+            let string = TomlString::Text {
+                text: "\rfive".into(),
+                literal: literal,
+                multiline: true,
+            };
+            assert_eq!(string.clean(), "\rfive");
+        }
+    }
+
+    #[test]
+    fn insert_sorted() {
+        let text = "[package]\nname = \"x\"\n\n[dependencies] # Sorted\n  # Logging\n\
+                    \x20 log = \"0.3\"\n\n  serde = \"1.0\" # Data\n  toml = \"0.4\"\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut deps = document.find_or_insert_table(&["dependencies"]).expect("No table");
+            deps.insert_sorted("regex", "0.2");
+            deps.insert_sorted("env_logger", "0.4");
+            deps.insert_sorted("yaml", "0.1");
+            deps.insert_sorted("toml", "0.5");
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[package]\nname = \"x\"\n\n[dependencies] # Sorted\n\
+                    \x20 env_logger = \"0.4\"\n  # Logging\n  log = \"0.3\"\n\n\
+                    \x20 regex = \"0.2\"\n  serde = \"1.0\" # Data\n  toml = \"0.5\"\n\
+                    \x20 yaml = \"0.1\"\n");
+
+        let mut document = space_toml::Document::new();
+        document.root().insert_sorted("b", 2);
+        document.root().insert_sorted("a", 1);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1\nb = 2\n");
+    }
+
+    #[test]
+    fn detect_indent() {
+        let text = "a = 1\n[server]\n  host = \"x\"\n  port = 80\n  [server.tls]\n\
+                    \x20   cert = \"c\"\n[[users]]\n  name = \"a\"\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.detect_indent(), Some("  "));
+        let text = "[a]\n\tx = 1\n\ty = { z = 2 }\n[b]\n    w = 3\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.detect_indent(), Some("\t"));
+        let text = "a = 1\n[b]\nc = [\n  1,\n]\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.detect_indent(), None);
+    }
+
+    #[test]
+    fn only_array_of_tables() {
+        let text = "[[items]]\nx = 1\n[[items]]\nx = 2\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        let root = document.root();
+        let items = root.get("items").expect("Array not found");
+        let values = items.array_of_tables()
+            .expect("Not an array of tables")
+            .map(|table| table.get("x").and_then(Value::int))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn normalize_equals_spacing() {
+        let text = "a=1\nb =2 # Two\n\n[t]\n  c  =  3\n  d = { e=4, f\t= [{ g =5 }] }\n\
+                    [[u]]\nh= 6\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert!(document.normalize_equals_spacing());
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "a = 1\nb = 2 # Two\n\n[t]\n  c = 3\n  d = { e = 4, f = [{ g = 5 }] }\n[[u]]\n\
+                    h = 6\n");
+        assert!(!document.normalize_equals_spacing());
+    }
+
+    #[test]
+    fn datetimes() {
+        use space_toml::PathPart;
+        let text = "created = 1979-05-27T07:32:00Z\nname = \"x\"\n[server]\n\
+                    window = { from = 1979-05-27, to = 1979-05-28 }\n\
+                    holidays = [2017-12-24, 2017-12-31]\n[[events]]\n\
+                    at = 1979-05-27T07:32:00-07:00\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        let datetimes = document.datetimes()
+            .map(|(path, text)| (PathPart::join(&path), text))
+            .collect::<Vec<_>>();
+        assert_eq!(datetimes,
+                   vec![("created".to_string(), "1979-05-27T07:32:00Z"),
+                        ("events[0].at".to_string(), "1979-05-27T07:32:00-07:00"),
+                        ("server.holidays[0]".to_string(), "2017-12-24"),
+                        ("server.holidays[1]".to_string(), "2017-12-31"),
+                        ("server.window.from".to_string(), "1979-05-27"),
+                        ("server.window.to".to_string(), "1979-05-28")]);
+    }
+
+    #[test]
+    fn tidy() {
+        let text = "a = 1   \n\n\n\n\nb = 2 \t\n  \n  \n  c = { x = 1 }\n[t]\n\n\n\nd = 4\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.for_each_table_mut(|path, table| {
+            if path.is_empty() {
+                table.push_space(" ");
+                table.push_space("");
+                table.push_space("  ");
+                if let Some(&mut Value::Table(ref mut inline)) = table.get_mut("c") {
+                    inline.push_space(" ");
+                    inline.push_space("\t");
+                    assert!(inline.tidy(1));
+                }
+            }
+            assert!(table.tidy(1));
+            assert!(!table.tidy(1));
+        });
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1\n\nb = 2\n\n  c = { x = 1  \t}\n[t]\n\nd = 4\n");
+
+        let mut table = space_toml::TableData::new_inline();
+        table.push_space(" ");
+        table.push_space(" ");
+        table.insert_spaced("a", 1, Some(""), Some(" "));
+        table.push_space("");
+        table.push_space(" ");
+        assert!(table.tidy(0));
+        let mut out = String::new();
+        table.write(&mut out);
+        assert_eq!(out, "{  a= 1 }");
+    }
+
+    #[test]
+    fn get_and_set_entry() {
+        let text = "[server]\nport    =  8080   # The port to listen on\nhost = \"x\"\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        {
+            let mut server = document.find_or_insert_table(&["server"]).expect("No table");
+            let port = {
+                let entry = server.get_entry("port").expect("Entry not found");
+                assert_eq!((entry.before_eq, entry.after_eq), ("    ", "  "));
+                assert_eq!(entry.comment, Some(" The port to listen on"));
+                entry.value.int().expect("Not an integer")
+            };
+            assert_eq!(server.get_entry("host").and_then(|entry| entry.comment), None);
+            assert!(server.set_entry("port", port + 1).is_some());
+            assert!(server.set_entry("missing", 1).is_none());
+            assert!(server.get("missing").is_none());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[server]\nport    =  8081   # The port to listen on\nhost = \"x\"\n");
+    }
+
+    #[test]
+    fn convert_array_of_tables() {
+        let text = "title = 'x'\n\n[[items]]\nname = \"a\"\n[items.size]\nw = 1\n\n\
+                    [[items]] # Second\nname = \"b\"\ntags = [1, 2]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let data = document.to_map();
+        assert!(document.inline_array_of_tables(&["items"]));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "title = 'x'\n\nitems = [{ name = \"a\", size = { w = 1 } }, \
+                    { name = \"b\", tags = [1, 2] }]\n");
+        assert_eq!(space_toml::parse(&out).expect("Reparsing failed").to_map(), data);
+        assert!(!document.inline_array_of_tables(&["items"]));
+
+        assert!(document.expand_inline_array(&["items"]));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "title = 'x'\n\n[[items]]\nname = \"a\"\nsize = { w = 1 }\n\n\
+                    [[items]]\nname = \"b\"\ntags = [1, 2]\n");
+        assert_eq!(space_toml::parse(&out).expect("Reparsing failed").to_map(), data);
+        assert!(!document.expand_inline_array(&["items"]));
+        assert!(!document.expand_inline_array(&["title"]));
+    }
+
+    #[test]
+    fn table_in_array_of_tables() {
+        let text = "[[a]]\nx = 0\n[[a]]\n[a.b]\nx = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        let root = document.root();
+        let array = root.get("a").and_then(Value::array).expect("Not an array");
+        assert_eq!(array.len(), 2);
+        assert!(array.get(0).and_then(Value::table).unwrap().get("b").is_none());
+        let b = array.get(1).and_then(Value::table).unwrap().get("b").and_then(Value::table);
+        assert_eq!(b.and_then(|b| b.get("x")).and_then(Value::int), Some(1));
+    }
+
+    #[test]
+    fn add_array_entry() {
+        use space_toml::TableData;
+        let text = "[[servers]]\nname = \"a\"\n\n\n[[servers]]\nname = \"b\"\n[servers.tls]\n\
+                    on = true\n\n[other]\nx = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut table = TableData::new_regular();
+        table.insert("name", "c");
+        document.add_array_entry(&["servers"], table).expect("Adding failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "[[servers]]\nname = \"a\"\n\n\n[[servers]]\nname = \"b\"\n[servers.tls]\n\
+                    on = true\n\n\n[[servers]]\nname = \"c\"\n\n[other]\nx = 1\n");
+
+        let mut document = space_toml::parse("a = 1\n").expect("Parsing failed");
+        let mut table = TableData::new_regular();
+        table.insert("b", 2);
+        document.add_array_entry(&["list"], table).expect("Adding failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 1\n\n[[list]]\nb = 2\n");
+        assert!(document.add_array_entry(&["a"], TableData::new_regular()).is_err());
+    }
+
+    #[test]
+    fn table_builder() {
+        use space_toml::TableBuilder;
+        let document = TableBuilder::new()
+            .key("name", "demo")
+            .key("version", 1)
+            .table("server", |t| {
+                t.key("host", "localhost")
+                    .key("port", 8080)
+                    .table("tls", |t| t.key("enabled", true))
+            })
+            .array_of_tables("bin", |entries| {
+                entries.push(TableBuilder::new().key("name", "a"));
+                entries.push(TableBuilder::new().key("name", "b").table("env", |t| t.key("x", 1)));
+            })
+            .build_document();
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "name = \"demo\"\nversion = 1\n\n[server]\nhost = \"localhost\"\nport = 8080\n\n\
+                    [server.tls]\nenabled = true\n\n[[bin]]\nname = \"a\"\n\n[[bin]]\n\
+                    name = \"b\"\n\n[bin.env]\nx = 1\n");
+
+        let table = TableBuilder::new().key("a", 1).table("t", |t| t.key("b", 2)).build();
+        assert_eq!(table.get("a").and_then(Value::int), Some(1));
+        let t = table.get("t").and_then(Value::table).expect("Not a table");
+        assert_eq!(t.get("b").and_then(Value::int), Some(2));
+    }
+
+    #[test]
+    fn set_value_from_str() {
+        let text = "a = 1 # One\n[t]\nb  =  'x'\nc = { d = true }\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.set_value_from_str(&["a"], " [2, 3] ").ok(), Some(true));
+        assert_eq!(document.set_value_from_str(&["t", "b"], "\"y\"").ok(), Some(true));
+        assert_eq!(document.set_value_from_str(&["t", "c", "d"], "false").ok(), Some(true));
+        assert_eq!(document.set_value_from_str(&["t"], "1").ok(), Some(false));
+        assert_eq!(document.set_value_from_str(&["missing"], "1").ok(), Some(false));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = [2, 3] # One\n[t]\nb  =  \"y\"\nc = { d = false }\n");
+
+        let err = document.set_value_from_str(&["a"], "[1, ]2").err().expect("Parsing succeeded");
+        assert_eq!(err.span(), Some(5..6));
+        let err = document.set_value_from_str(&["a"], "tru").err().expect("Parsing succeeded");
+        assert_eq!(err.span(), Some(0..1));
+        let mut out = String::new();
+        document.write(&mut out);
+        assert!(out.starts_with("a = [2, 3] # One\n"));
+    }
+
+    #[test]
+    fn is_modified() {
+        let mut document = space_toml::parse("a = 1\n").expect("Parsing failed");
+        assert!(!document.is_modified());
+        assert_eq!(document.root().get_int("a"), Some(1));
+        assert!(!document.is_modified());
+        document.root().insert_smart("b", 2);
+        assert!(document.is_modified());
+        document.reset_modified();
+        assert!(!document.is_modified());
+        document.root().remove(&"b".into());
+        assert!(document.is_modified());
+        assert!(!space_toml::Document::new().is_modified());
+
+        // Parsing doesn't count as an edit, while edits that are undone do
+        let text = "\u{FEFF}a = 1\n[t]\n[[t.x]]\n[[t.x]]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert!(!document.is_modified());
+        assert!(document.find_or_insert_table(vec!["t"]).is_ok());
+        assert!(document.root().remove(&"missing".into()).is_none());
+        assert!(!document.is_modified());
+        document.root().insert("b", 2);
+        document.root().remove(&"b".into());
+        assert!(document.is_modified());
+        document.reset_modified();
+        document.find_or_insert_table(vec!["u"]).unwrap();
+        assert!(document.is_modified());
+    }
+
+    #[test]
+    fn reads_borrow_the_source() {
+        use std::borrow::Cow;
+        let text = "plain = \"no escapes\"\n\"quoted key\" = 'literal'\n\
+                    multi = \"\"\"\nlines\nhere\"\"\"\nescaped = \"a\\tb\"\nint = 1_000\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        for (key, _) in root.iter() {
+            match key.normalized() {
+                Cow::Borrowed(_) => {}
+                Cow::Owned(key) => panic!("The key {:?} was copied", key),
+            }
+        }
+        let string = |key| root.get(key).and_then(Value::string).expect("Not a string");
+        match string("plain") {
+            Cow::Borrowed(text) => assert_eq!(text, "no escapes"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match string("quoted key") {
+            Cow::Borrowed(text) => assert_eq!(text, "literal"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match string("multi") {
+            Cow::Borrowed(text) => assert_eq!(text, "lines\nhere"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match string("escaped") {
+            Cow::Owned(text) => assert_eq!(text, "a\tb"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(root.get_int("int"), Some(1000));
+    }
+
+    #[test]
+    fn clean_basic_strings() {
+        use std::borrow::Cow;
+        let text = "a = \"hello\"\nb = \"a\\nb\"\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        match root.get_str("a") {
+            Some(Cow::Borrowed("hello")) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match root.get_str("b") {
+            Some(Cow::Owned(ref text)) if text == "a\nb" => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn values_of_type() {
+        use space_toml::{PathPart, TomlType};
+        let document = space_toml::parse(include_str!("../samples/example.toml"))
+            .expect("Parsing failed");
+        let ints = document.values_of_type(TomlType::Integer)
+            .map(|(path, value)| (PathPart::join(&path), value.int().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(ints,
+                   vec![("clients.data[1][0]".to_string(), 1),
+                        ("clients.data[1][1]".to_string(), 2),
+                        ("database.connection_max".to_string(), 5000),
+                        ("database.ports[0]".to_string(), 8001),
+                        ("database.ports[1]".to_string(), 8001),
+                        ("database.ports[2]".to_string(), 8002),
+                        ("products[0].sku".to_string(), 738594937),
+                        ("products[1].sku".to_string(), 284758393)]);
+        let bools = document.values_of_type(TomlType::Bool).map(|(path, _)| PathPart::join(&path));
+        assert_eq!(bools.collect::<Vec<_>>(), vec!["database.enabled".to_string()]);
+        assert_eq!(document.values_of_type(TomlType::Table).count(), 8);
+    }
+
+    #[test]
+    fn dotted_headers_stay_dotted() {
+        let texts = ["[a.b]\nx=1", "[a.b]\nx = 1\n\n[a]\ny = 2\n", "[a . 'b']\n[a]\n[a.c]\n"];
+        for text in &texts {
+            let document = space_toml::parse(text).expect("Parsing failed");
+            let mut out = String::new();
+            document.write(&mut out);
+            assert_eq!(&out, text);
+        }
+
+        let mut document = space_toml::parse("[a.b]\nx = 1\n[a]\ny = 2\n").expect("Parsing failed");
+        document.find_or_insert_table(&["a", "b"]).unwrap().insert_smart("z", 3);
+        document.find_or_insert_table(&["a"]).unwrap().insert_smart("w", 4);
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[a.b]\nx = 1\nz = 3\n[a]\ny = 2\nw = 4\n");
+    }
+
+    #[test]
+    fn resolve_includes() {
+        use std::collections::HashMap;
+        use space_toml::IncludeError;
+
+        let mut files = HashMap::new();
+        files.insert("base.toml",
+                     "include = \"defaults.toml\"\nname = \"base\"\nversion = 2\n\n\
+                      [server]\nhost = \"localhost\"\nport = 80\n");
+        files.insert("defaults.toml", "debug = false\n\n[logging]\nlevel = \"info\"\n");
+        files.insert("loop.toml", "include = [\"defaults.toml\", \"loop.toml\"]\n");
+
+        let text = "include = \"base.toml\"\nname = \"app\"\n\n[server]\nport = 8080\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        document.resolve_includes("include", |name| files.get(name).cloned())
+            .expect("Resolving failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "name = \"app\"\nversion = 2\ndebug = false\n\n[server]\nport = 8080\n\
+                    host = \"localhost\"\n\n[logging]\nlevel = \"info\"\n");
+
+        let mut document = space_toml::parse("include = 'loop.toml'").unwrap();
+        match document.resolve_includes("include", |name| files.get(name).cloned()) {
+            Err(IncludeError::Cycle(ref name)) if name == "loop.toml" => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let mut document = space_toml::parse("include = 'missing.toml'").unwrap();
+        match document.resolve_includes("include", |name| files.get(name).cloned()) {
+            Err(IncludeError::NotFound(ref name)) if name == "missing.toml" => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn array_layout() {
+        let text = "single = [1, 2]\nwrapped = [\n  'a', # first\n  'b',\n]\n\
+                    nested = [[1,\n 2], [3]]\nempty = []\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        let array = |key| match root.get(key) {
+            Some(&Value::Array(ref array)) => array,
+            other => panic!("Not an array: {:?}", other),
+        };
+        let single = array("single");
+        assert_eq!((single.len(), single.is_multiline(), single.has_comments()), (2, false, false));
+        let wrapped = array("wrapped");
+        assert_eq!((wrapped.len(), wrapped.is_multiline(), wrapped.has_comments()),
+                   (2, true, true));
+        // Only the newlines of the array itself count
+        let nested = array("nested");
+        assert_eq!((nested.len(), nested.is_multiline(), nested.has_comments()), (2, false, false));
+        let empty = array("empty");
+        assert_eq!((empty.len(), empty.is_multiline(), empty.has_comments()), (0, false, false));
+    }
+
+    #[test]
+    fn radix_integers() {
+        use space_toml::{Radix, RadixInt, NegativeRadixInt};
+        let mut document = space_toml::parse("mode = 0o644\n").expect("Parsing failed");
+        {
+            let mut root = document.root();
+            root.insert_smart("mask", Value::int_hex(255).unwrap());
+            root.insert_smart("dir_mode", Value::int_oct(0o755).unwrap());
+            root.insert_smart("flags", Value::int_bin(10).unwrap());
+            root.insert_smart("zero", Value::int_hex(0).unwrap());
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "mode = 0o644\nmask = 0xFF\ndir_mode = 0o755\nflags = 0b1010\nzero = 0x0\n");
+
+        let mut reparsed = space_toml::parse(&out).expect("Parsing failed");
+        let root = reparsed.root();
+        let values = ["mode", "mask", "dir_mode", "flags", "zero"]
+            .iter()
+            .map(|key| root.get_int(*key).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0o644, 255, 0o755, 10, 0]);
+        assert_eq!(Value::int_hex(255).unwrap().int(), Some(255));
+        match Value::int_oct(0o755).unwrap() {
+            Value::Int(ref int) => assert_eq!(int.value(), 0o755),
+            ref other => panic!("Unexpected value: {:?}", other),
+        }
+        let int = RadixInt::new(10, Radix::Bin).unwrap();
+        assert_eq!((int.value(), int.radix()), (10, Radix::Bin));
+
+        assert_eq!(Value::int_hex(-1).err(), Some(NegativeRadixInt(-1)));
+        assert_eq!(Value::int_oct(-8).err().map(|err| err.to_string()),
+                   Some("-8 can't be written with a radix prefix".to_string()));
+        assert!(RadixInt::new(-2, Radix::Bin).is_err());
+    }
+
+    #[test]
+    fn find_or_insert_empty_path() {
+        use space_toml::InsertTableError;
+        let text = "a = 1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        match document.find_or_insert_table(&[] as &[&str]) {
+            Err(InsertTableError::EmptyPath) => {}
+            other => panic!("Unexpected result: {:?}", other.map(|table| table.is_empty())),
+        }
+        match document.find_or_insert_table(&["a", "b"]) {
+            Err(InsertTableError::PathItemNotTable(ref key)) if key == "a" => {}
+            other => panic!("Unexpected result: {:?}", other.map(|table| table.is_empty())),
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+    }
+
+    #[test]
+    fn table_comment() {
+        let text = "title = 'x'\n\n# Network settings\n# for the server\n[server] # main\n\
+                    # The port\nport = 80\n\n[client]\n# Not about a header\n\n\
+                    [database]\n\n# A job\n[[jobs]]\nname = 'a'\n\n# Another job\n[[jobs]]\n\
+                    name = 'b'\n";
+        let document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.table_comment(&["server"]), Some(" for the server"));
+        assert_eq!(document.table_comment(&["client"]), None);
+        assert_eq!(document.table_comment(&["database"]), None);
+        assert_eq!(document.table_comment(&["jobs"]), Some(" A job"));
+        assert_eq!(document.table_comment(&["missing"]), None);
+    }
+
+    #[test]
+    fn parse_until() {
+        let text = "title = 'x'\n\n[package]\nname = \"demo\"\n\n[package.metadata]\nkey = 1\n\n\
+                    [dependencies]\nthis is not = valid TOML\n";
+        let mut document = space_toml::parse_until(text, &["package"])
+            .expect("Parsing failed")
+            .expect("Table not found");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out,
+                   "title = 'x'\n\n[package]\nname = \"demo\"\n\n[package.metadata]\nkey = 1\n\n");
+        let table = document.find_or_insert_table(&["package"]).unwrap();
+        assert_eq!(table.get_str("name").unwrap(), "demo");
+        assert_eq!(table.get_path(&["metadata", "key"]).and_then(Value::int), Some(1));
+
+        let text = "[a.b]\nx = 1\n[[c]]\n[[c]]\n[d]\n";
+        assert!(space_toml::parse_until(text, &["a"]).unwrap().is_some());
+        let document = space_toml::parse_until(text, &["c"]).unwrap().unwrap();
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "[a.b]\nx = 1\n[[c]]\n[[c]]\n");
+        assert!(space_toml::parse_until(text, &["e"]).unwrap().is_none());
+        assert!(space_toml::parse_until("[a]\nb = \n", &["a"]).is_err());
+    }
+
+    #[test]
+    fn join_path() {
+        use std::borrow::Cow;
+        use space_toml::{join_path, quote_path_segment};
+        assert_eq!(join_path(&["servers", "b.c", "host name", "port"]),
+                   "servers.\"b.c\".\"host name\".port");
+        assert_eq!(join_path(&["a"]), "a");
+        assert_eq!(join_path(&[]), "");
+        assert_eq!(join_path(&["", "say \"hi\""]), "\"\".\"say \\\"hi\\\"\"");
+        match quote_path_segment("plain-key") {
+            Cow::Borrowed("plain-key") => {}
+            other => panic!("Unexpected segment: {:?}", other),
+        }
+        assert_eq!(quote_path_segment("a.b"), "\"a.b\"");
+
+        // The joined path is read back as the same keys
+        let text = format!("[{}]\nx = 1\n", join_path(&["a", "b.c", "d e"]));
+        let mut document = space_toml::parse(&text).expect("Parsing failed");
+        let table = document.find_or_insert_table(&["a", "b.c", "d e"]).unwrap();
+        assert_eq!(table.get_int("x"), Some(1));
+    }
+
+    #[test]
+    fn raw_text() {
+        let text = "int = 1_000\nhex = 0xdead_beef\nfloat = 6_626.0e-37\n\
+                    date = 1979-05-27T07:32:00-08:00\nstring = \"a\\tb\"\nbool = true\n\
+                    array = [1]\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut root = document.root();
+        let raw = |key| root.get(key).unwrap().raw_text();
+        assert_eq!(raw("int"), Some("1_000"));
+        assert_eq!(raw("hex"), Some("0xdead_beef"));
+        assert_eq!(raw("float"), Some("6_626.0e-37"));
+        assert_eq!(raw("date"), Some("1979-05-27T07:32:00-08:00"));
+        assert_eq!(raw("string"), Some("a\\tb"));
+        assert_eq!(raw("bool"), None);
+        assert_eq!(raw("array"), None);
+
+        root.set_entry("int", 1000);
+        assert_eq!(root.get("int").unwrap().raw_text(), None);
+        assert_eq!(Value::basic_string("a\tb").raw_text(), None);
+        assert_eq!(Value::owned_datetime("1979-05-27".to_string()).raw_text(), None);
+    }
+
+    #[test]
+    fn get_or_insert_with_is_written() {
+        use space_toml::TableData;
+        let mut document = space_toml::parse("a = 1\n[t]\nb = 2\n").expect("Parsing failed");
+        {
+            let mut root = document.root();
+            *root.get_or_insert_with("a".into(), || Value::from(5)) = Value::from(10);
+            root.get_or_insert_with("count".into(), || Value::from(0));
+            let value = root.get_or_insert_with("sub".into(), || TableData::new_regular().into());
+            value.table_mut().unwrap().insert("c", 3);
+        }
+        {
+            let mut table = document.find_or_insert_table(&["t"]).unwrap();
+            table.get_or_insert_with("name".into(), || Value::basic_string("x"));
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "a = 10\ncount = 0\n[t]\nb = 2\nname = \"x\"\n\n[sub]\nc = 3\n");
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let text = "\u{FEFF}# Settings\na = 1\n\n[b]\nc = 2\n";
+        let mut doc = space_toml::parse(text).unwrap();
+        assert!(doc.has_bom());
+        let mut out = String::new();
+        doc.write(&mut out);
+        assert_eq!(out, text);
+        doc.set_bom(true);
+        assert!(!doc.is_modified());
+
+        doc.set_bom(false);
+        assert!(doc.is_modified());
+        let mut out = String::new();
+        doc.write(&mut out);
+        assert_eq!(out, "# Settings\na = 1\n\n[b]\nc = 2\n");
+
+        let doc = space_toml::parse("a = 1\n").unwrap();
+        assert!(!doc.has_bom());
+        assert!(space_toml::parse("a = 1\n\u{FEFF}b = 2\n").is_err());
+    }
+
+    #[test]
+    fn radix_integers_keep_their_text() {
+        let text = "a = 0xDeadBeef\nb = 0xabc\nc = 0xABC\nd = 0o755\ne = 0b1010\nf = 0xff_ff\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+
+        let root = document.root();
+        let values = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|key| root.get_int(*key).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0xDEADBEEF, 0xABC, 0xABC, 0o755, 10, 0xFFFF]);
+    }
+
+    #[test]
+    fn plan_insert() {
+        use space_toml::InsertTableError;
+        let text = "[a]\nx = 1\n[[servers]]\nname = \"alpha\"\n";
+        fn names<K: ToString>(paths: Vec<Vec<K>>) -> Vec<String> {
+            paths.iter()
+                .map(|path| path.iter().map(K::to_string).collect::<Vec<_>>().join("."))
+                .collect()
+        }
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(names(document.plan_insert(&["a", "b", "c"]).unwrap()), vec!["a.b", "a.b.c"]);
+        assert_eq!(names(document.plan_insert(&["servers", "tls"]).unwrap()), vec!["servers.tls"]);
+        assert_eq!(names(document.plan_insert(&["d"]).unwrap()), vec!["d"]);
+        assert!(document.plan_insert(&["a"]).unwrap().is_empty());
+        match document.plan_insert(&["a", "x", "y"]) {
+            Err(InsertTableError::PathItemNotTable(ref key)) if key == "x" => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match document.plan_insert(&[]) {
+            Err(InsertTableError::EmptyPath) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(!document.is_modified());
+
+        document.find_or_insert_table(vec!["a", "b", "c"]).unwrap();
+        assert!(document.plan_insert(&["a", "b", "c"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn datetime_tokens() {
+        use space_toml::Token;
+        let text = "a = 1979-05-27T07:32:00Z\nb = 1979-05-27t07:32:00.5\nc = 1979-05-27\n\
+                    d = 07:32:00\ne = [1979-05-27 07:32:00+01:00]\n";
+        let datetimes = space_toml::tokens(text)
+            .map(|token| token.expect("Lexing failed").1)
+            .filter_map(|token| match token {
+                Token::OffsetDateTime(text) => Some(("offset", text)),
+                Token::LocalDateTime(text) => Some(("datetime", text)),
+                Token::LocalDate(text) => Some(("date", text)),
+                Token::LocalTime(text) => Some(("time", text)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(datetimes,
+                   vec![("offset", "1979-05-27T07:32:00Z"),
+                        ("datetime", "1979-05-27t07:32:00.5"),
+                        ("date", "1979-05-27"),
+                        ("time", "07:32:00"),
+                        ("offset", "1979-05-27 07:32:00+01:00")]);
+
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        assert_eq!(document.root().get("d").and_then(Value::datetime), Some("07:32:00"));
+    }
+
+    #[test]
+    fn special_floats() {
+        use std::f64;
+        let text = "a = inf\nb = +inf\nc = -inf\nd = nan\ne = -nan\nf = [+nan, inf]\n";
+        let mut lexed = String::new();
+        for token in space_toml::tokens(text) {
+            token.expect("Lexing failed").1.write(&mut lexed);
+        }
+        assert_eq!(lexed, text);
+
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, text);
+        {
+            let root = document.root();
+            assert_eq!(root.get_float("a"), Some(f64::INFINITY));
+            assert_eq!(root.get_float("b"), Some(f64::INFINITY));
+            assert_eq!(root.get_float("c"), Some(f64::NEG_INFINITY));
+            assert!(root.get_float("d").unwrap().is_nan());
+            assert!(root.get_float("e").unwrap().is_nan());
+        }
+
+        let mut document = space_toml::parse("").expect("Parsing failed");
+        {
+            let mut root = document.root();
+            root.insert_smart("x", f64::NAN);
+            root.insert_smart("y", f64::NEG_INFINITY);
+            let scientific = space_toml::FloatFormat::Scientific;
+            root.insert_smart("z", Value::float_with_format(f64::NAN, scientific));
+        }
+        let mut out = String::new();
+        document.write(&mut out);
+        assert_eq!(out, "x = nan\ny = -inf\nz = nan\n");
+        assert!(space_toml::parse(&out).is_ok());
+    }
+}
+
+pub mod invalid {
+    use space_toml::{self, ErrorKind};
+
+    #[test]
+    fn empty_table_header() {
+        match space_toml::parse("[]\na = 1\n").err().map(|e| e.kind) {
+            Some(ErrorKind::EmptyScope { pos: 0 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let err = space_toml::parse("a = 1\n[ ]").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Empty table header at 2:1"));
+    }
+
+    #[test]
+    fn empty_array_of_tables_header() {
+        match space_toml::parse("a = 1\n[[]]\n").err().map(|e| e.kind) {
+            Some(ErrorKind::EmptyScope { pos: 6 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    /// Parses the text and formats the error (if any), which should never panic.
+    fn parse_without_panic(text: &str) {
+        if let Err(err) = space_toml::parse(text) {
+            let _ = format!("{}", err);
+        }
+    }
+
+    #[test]
+    fn adversarial_inputs() {
+        let inputs = ["", "[", "]", "[[", "]]", "}", "{", "=", "a", "a =", "a = ", "a =\n",
+                      "a = [", "a = [1,", "a = [,]", "a = {", "a = {b", "a = {b =", "[a", "[a.",
+                      "[a.]", "[[a]", "[[a]]]", "[a]]", "a = 1\n[[a]]", "[[a]]\n[a]",
+                      "a = [1]\n[[a]]", "a = []\n[[a]]", "a = {}\n[a]", "[a]\n[a.b]\n[[a.b]]",
+                      "\r", "\r\n", "a = 1\r", "\"", "'", "\"\"\"", "'''", "a = \"\\", "a = \"\\u12",
+                      "a = \"\\uD800\"", "a = \"\\q\"", "a = 1_", "a = _1", "a = 1e", "a = 1.",
+                      "a = 1979-05-27T", "a = t", "a = f", "a = tru", "# \u{e9}", "\u{e9} = 1",
+                      "a = \"\u{e9}\"\n\u{e9}", "a = 1 2", "a.b = 1", "[a . b . ]", "[ ]", "[[ ]]",
+                      "a = 0x", "a = 0x_1", "a = 0xG", "a = 0b2", "a = 0o1_", "a = -0x1"];
+        for input in inputs.iter() {
+            parse_without_panic(input);
+        }
+    }
+
+    #[test]
+    fn random_inputs() {
+        let alphabet = ["[", "]", "[[", "]]", "{", "}", "=", ",", ".", " ", "\n", "\r\n", "\r",
+                        "#", "\"", "'", "\\", "a", "b", "1", "_", "-", "e", "true", "\u{e9}"];
+        let mut state: u32 = 12345;
+        for _ in 0..20000 {
+            let mut input = String::new();
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let len = (state >> 16) % 16;
+            for _ in 0..len {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                input.push_str(alphabet[(state >> 16) as usize % alphabet.len()]);
+            }
+            parse_without_panic(&input);
+        }
+    }
+
+    #[test]
+    fn array_kind_conflict() {
+        match space_toml::parse("a = []\n[[a]]\n").err().map(|e| e.kind) {
+            Some(ErrorKind::ArrayKindConflict { pos: 7, original: 0 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match space_toml::parse("[[t.a]]\n[t]\na = [1, 2]\n").err().map(|e| e.kind) {
+            Some(ErrorKind::ArrayKindConflict { pos: 12, original: 0 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let err = space_toml::parse("a = [{ b = 1 }]\n[[a]]").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Array at 2:1 conflicts"));
+    }
+
+    #[test]
+    fn stray_closing_bracket() {
+        use space_toml::LexerErrorKind;
+        let cases = [("a = 1\n]\nb = 2\n", 6), ("]", 0), ("[t]\n  ]", 6), ("a = 1\n]]\n", 6)];
+        for &(text, pos) in cases.iter() {
+            let err = space_toml::parse(text).err().expect("Parsing succeeded");
+            match err.kind {
+                ErrorKind::Lex(ref err) => {
+                    match err.kind {
+                        LexerErrorKind::UnmatchedClosingBrace { pos: found } => {
+                            assert_eq!(found, pos)
+                        }
+                        ref other => panic!("Unexpected lexer error: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        let err = space_toml::parse("a = 1\n]\n").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Unmatched brace found at 2:1"));
+    }
+
+    #[test]
+    fn invalid_escape_message() {
+        let err = space_toml::parse("a = \"ab\\qc\"").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Invalid escape '\\q' at 1:9"));
+        let err = space_toml::parse("a = \"\\u12G4\"").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Invalid character 'G' in escape at 1:10"));
+    }
+
+    #[test]
+    fn trailing_whitespace() {
+        use space_toml::ParseOptions;
+        let text = "a = 1\nb = [\n  2, \n]\n[t]  \nc = 3";
+        assert!(space_toml::parse(text).is_ok());
+        let mut options = ParseOptions::new();
+        options.deny_trailing_whitespace = true;
+        match space_toml::parse_with(text, &options).err().map(|e| e.kind) {
+            Some(ErrorKind::TrailingWhitespace { pos: 16 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let err = space_toml::parse_with("a = 1 \r\n", &options).err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Trailing whitespace at 1:6"));
+        assert!(space_toml::parse_with("a = 1 # note\n  b = 2\n", &options).is_ok());
+    }
+
+    #[test]
+    fn strict_validation() {
+        use space_toml::ParseOptions;
+        let strict = ParseOptions::strict();
+        let text = "a = 1\nb = 2\na = 3\n";
+        assert!(space_toml::parse(text).is_ok());
+        match space_toml::parse_with(text, &strict).err().map(|e| e.kind) {
+            Some(ErrorKind::KeyDefinedTwice { pos: 12, original: 0 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let cases = [("t = { x = 1, x = 2 }", "Key at 1:14 was already defined at 1:7"),
+                     ("[t]\na = 1\n[t]\nb = 2\n", "Table at 3:1 was already defined at 1:1"),
+                     ("a = 007", "Number with leading zero at 1:5"),
+                     ("a = -0_1.5", "Number with leading zero at 1:5")];
+        for &(text, message) in &cases {
+            assert!(space_toml::parse(text).is_ok(), "Lenient parse failed: {:?}", text);
+            let err = space_toml::parse_with(text, &strict).err().expect("Parsing succeeded");
+            let found = format!("{}", err);
+            assert!(found.starts_with(message), "Unexpected error: {}", found);
+        }
+        let valid = "[[p]]\n[p.q]\na = 0\n[[p]]\n[p.q]\nb = 0.5\nc = 'x'\n";
+        assert!(space_toml::parse_with(valid, &strict).is_ok());
+    }
+
+    #[test]
+    fn deny_newlines_in_strings() {
+        use space_toml::ParseOptions;
+        let mut options = ParseOptions::new();
+        options.deny_newlines_in_strings = true;
+        for &(text, pos) in &[("a = \"x\ny\"", 6), ("a = 'x\r\ny'", 6)] {
+            assert!(space_toml::parse(text).is_ok(), "Lenient parse failed: {:?}", text);
+            match space_toml::parse_with(text, &options).err().map(|e| e.kind) {
+                Some(ErrorKind::NewlineInString { pos: found }) => assert_eq!(found, pos),
+                other => panic!("Unexpected result for {:?}: {:?}", text, other),
+            }
+        }
+        let valid = "a = \"\"\"x\ny\"\"\"\nb = '''\r\n'''\nc = \"x\\ny\"\n";
+        assert!(space_toml::parse_with(valid, &options).is_ok());
+    }
+
+    #[test]
+    fn table_with_pound() {
+        let text = include_str!("invalid/table-with-pound.toml");
+        assert!(space_toml::parse(text).is_err());
+    }
+
+    #[test]
+    fn key_without_value_at_eof() {
+        for &(text, start) in &[("key", 0), ("key ", 0), ("key =", 0), ("key = ", 0),
+                                ("a = 1\n  key", 8), ("a = 1\n\"key\"\t", 6),
+                                ("[t]\nkey = ", 4)] {
+            match space_toml::parse(text).err().map(|e| e.kind) {
+                Some(ErrorKind::UnfinishedItem { start: found }) => {
+                    assert_eq!(found, start, "Wrong position for {:?}", text)
+                }
+                other => panic!("Unexpected result for {:?}: {:?}", text, other),
+            }
+        }
+        let err = space_toml::parse("a = 1\n  key = ").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("No value found for key at 2:3"));
+    }
+
+    #[test]
+    fn tabs_in_headers() {
+        use space_toml::ParseOptions;
+        let text = "[a]\nb = 1\t\n[[ c . d]]\n[ e .\tf ]\n";
+        assert!(space_toml::parse(text).is_ok());
+        assert!(space_toml::parse_with(text, &ParseOptions::strict()).is_ok());
+        let options = ParseOptions { deny_tabs_in_headers: true, ..ParseOptions::strict() };
+        match space_toml::parse_with(text, &options).err().map(|e| e.kind) {
+            Some(ErrorKind::TabInHeader { pos: 27 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let err = space_toml::parse_with("[\ta]", &options).err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Tab in table header at 1:2"));
+    }
+
+    #[test]
+    fn newline_in_inline_table() {
+        let text = "a = { x = 1, s = \"\"\"line1\nline2\"\"\" }\n";
+        match space_toml::parse(text).err().map(|e| e.kind) {
+            Some(ErrorKind::NewlineInInlineTable { pos: 17 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let text = "a = 1\nb = { c = { d = [\n1] } }";
+        let err = space_toml::parse(text).err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Newline in inline table value at 2:17"));
+        let text = "a = { s = \"\"\"one line\"\"\", t = [1, 2] }\n";
+        assert!(space_toml::parse(text).is_ok());
+    }
+
+    #[test]
+    fn plus_in_bare_key() {
+        use space_toml::LexerErrorKind;
+        let cases = [("a+b = 1", 1), ("x = 1\n+a = 2\n", 6), ("[t]\nkey+ = 1\n", 7)];
+        for &(text, pos) in cases.iter() {
+            let err = space_toml::parse(text).err().expect("Parsing succeeded");
+            match err.kind {
+                ErrorKind::Lex(ref err) => {
+                    match err.kind {
+                        LexerErrorKind::InvalidKeyCharacter { pos: found } => {
+                            assert_eq!(found, pos)
+                        }
+                        ref other => panic!("Unexpected lexer error: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        let err = space_toml::parse("a+b = 1").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Invalid key character at 1:2"));
+    }
+
+    #[test]
+    fn leading_comma_in_array() {
+        for &text in &["a = [, 1]", "a = [,]", "a = [ # Note\n  ,1]"] {
+            match space_toml::parse(text).err().map(|e| e.kind) {
+                Some(ErrorKind::NonFinalComma { pos }) => assert_eq!(&text[pos..pos + 1], ","),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+        let err = space_toml::parse("a = [, 1]").err().expect("Parsing succeeded");
+        assert!(format!("{}", err).starts_with("Comma before any values at 1:6"));
+        match space_toml::parse("a = [1,, 2]").err().map(|e| e.kind) {
+            Some(ErrorKind::DoubleCommaInArray { start: 4, pos: 7 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(space_toml::parse("a = [1, 2,]").is_ok());
+    }
+
+    #[test]
+    fn unified_errors() {
+        use space_toml::TomlError;
+        let text = "a = 1\nb = \"unclosed\n";
+        let err = TomlError::from(space_toml::parse(text).err().expect("Parsing succeeded"));
+        match err {
+            TomlError::Lex(_) => {}
+            _ => panic!("Expected a lexing error, found {:?}", err),
+        }
+        assert_eq!(err.span(), Some(10..text.len()));
+        assert_eq!(err.message(), "Unclosed string starting at 2:5");
+        let text = "a = 1\n[]\n";
+        let err = TomlError::from(space_toml::parse(text).err().expect("Parsing succeeded"));
+        match err {
+            TomlError::Parse(_) => {}
+            _ => panic!("Expected a parsing error, found {:?}", err),
+        }
+        assert_eq!(err.span(), Some(6..7));
+        assert_eq!(err.message(), "Empty table header at 2:1");
+        assert!(err.to_string().starts_with("Empty table header at 2:1 :\n"));
+        let text = "a = [1, \"é\"]";
+        let err = TomlError::from(space_toml::parse(text).err().expect("Parsing succeeded"));
+        assert_eq!(&text[err.span().unwrap()], "\"");
+        let text = "a+b = 1";
+        let err = space_toml::tokens(text)
+            .filter_map(Result::err)
+            .next()
+            .expect("Lexing succeeded");
+        assert_eq!(err.span(), 1..2);
+        assert_eq!(err.message(), "Invalid key character at 1:2");
+        let err = TomlError::from(err);
+        assert_eq!(err.span(), Some(1..2));
+        assert_eq!(err.message(), "Invalid key character at 1:2");
+    }
+
+    #[test]
+    fn table_below_value() {
+        for text in vec!["a = 1\n[a.b]\nx = 1\n", "a = 'b'\n[[a.b]]\n", "a = [{}]\n[a.b]\n"] {
+            match space_toml::parse(text).err().map(|e| e.kind) {
+                Some(ErrorKind::InvalidScopePath) => {}
+                other => panic!("Unexpected result for {:?}: {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_array_types() {
+        let err = space_toml::parse("a = [1, \"x\", 3]\n").err().expect("Parsing succeeded");
+        match err.kind {
+            ErrorKind::WrongValueTypeInArray { start: 4, pos: 8, ref message } => {
+                assert_eq!(message, "array of integers, found string");
+            }
+            ref other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(err.message(), "Expected an array of integers, found string at 1:9");
+        let err = space_toml::parse("a = [\n  1.0,\n  true,\n]").err().expect("Parsing succeeded");
+        assert_eq!(err.message(), "Expected an array of floats, found bool at 3:3");
+    }
+
+    #[test]
+    fn empty_key_segment_in_header() {
+        for &(text, pos) in &[("[a.]\n", 3), ("x = 1\n[.a]\n", 7), ("[a..b]\n", 3),
+                              ("[[a. ]]\n", 5), ("[ . a]\n", 2)] {
+            let err = space_toml::parse(text).err().expect("Parsing succeeded");
+            match err.kind {
+                ErrorKind::EmptyKeySegment { pos: found, .. } if found == pos => {}
+                ref other => panic!("Unexpected result for {:?}: {:?}", text, other),
+            }
+            assert!(err.message().starts_with("Empty key segment in table header at "),
+                    "{}",
+                    err);
+        }
+        let err = space_toml::parse("[a]]\n").err().expect("Parsing succeeded");
+        assert_eq!(err.message(), "Invalid scope found at 1:3");
+    }
+
+    #[test]
+    fn diagnostic() {
+        use std::collections::BTreeMap;
+        use rustc_serialize::json::Json;
+        use space_toml::Diagnostic;
+
+        let err = space_toml::parse("a = 1\nb [2]\n").err().expect("Parsing succeeded");
+        let diagnostic = err.diagnostic();
+        assert_eq!(diagnostic,
+                   Diagnostic {
+                       kind: "MissingEquals",
+                       message: "'=' expected at 2:3".to_string(),
+                       line: Some(2),
+                       col: Some(3),
+                       span: Some(8..9),
+                   });
+
+        let mut map = BTreeMap::new();
+        map.insert("kind".to_string(), Json::String(diagnostic.kind.to_string()));
+        map.insert("message".to_string(), Json::String(diagnostic.message.clone()));
+        map.insert("line".to_string(), Json::U64(diagnostic.line.unwrap() as u64));
+        map.insert("col".to_string(), Json::U64(diagnostic.col.unwrap() as u64));
+        let span = diagnostic.span.clone().unwrap();
+        map.insert("span".to_string(),
+                   Json::Array(vec![Json::U64(span.start as u64), Json::U64(span.end as u64)]));
+        assert_eq!(Json::Object(map).to_string(),
+                   "{\"col\":3,\"kind\":\"MissingEquals\",\"line\":2,\
+                    \"message\":\"'=' expected at 2:3\",\"span\":[8,9]}");
+
+        let err = space_toml::parse("a = \"x").err().expect("Parsing succeeded");
+        assert_eq!(err.diagnostic().kind, "UnclosedString");
+        assert_eq!(err.diagnostic().span, Some(4..6));
+    }
+
+    #[test]
+    fn uppercase_radix_prefix() {
+        use space_toml::LexerErrorKind;
+        for text in &["a = 0XFF\n", "a = 0O755\n", "a = 0B1010\n"] {
+            let err = space_toml::parse(text).err().expect(text);
+            match err.kind {
+                ErrorKind::Lex(ref err) => {
+                    match err.kind {
+                        LexerErrorKind::InvalidIntCharacter { start: 4, pos: 5 } => {}
+                        ref other => panic!("Unexpected lexer error: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_datetime() {
+        use space_toml::LexerErrorKind;
+        let cases = [("a = 1979-99-99\n", 4),
+                     ("a = 1979-05-27T25:00:00Z\n", 15),
+                     ("a = 1979-05-27T07:32:00+7\n", 23),
+                     ("a = 1979-05-27T07:32Z\n", 15),
+                     ("a = 1979-05-2707:32:00Z\n", 14),
+                     ("a = 07:32\n", 4)];
+        for &(text, pos) in cases.iter() {
+            let err = space_toml::parse(text).err().expect(text);
+            match err.kind {
+                ErrorKind::Lex(ref err) => {
+                    match err.kind {
+                        LexerErrorKind::InvalidDateTime { start: 4, pos: found } => {
+                            assert_eq!(found, pos, "{:?}", text)
+                        }
+                        ref other => panic!("Unexpected lexer error: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        let err = space_toml::parse("a = 1\nb = 1979-02-30\n").err().expect("Parsing succeeded");
+        assert_eq!(err.message(), "Invalid datetime at 2:5");
+    }
+
+    #[test]
+    fn invalid_unicode_escape() {
+        use space_toml::LexerErrorKind;
+        let cases = [("a = \"\\uD800\"", 6),
+                     ("a = \"x\\uDFFF\"", 7),
+                     ("a = \"\"\"\\uDBFF\"\"\"", 8),
+                     ("a = \"\\U00110000\"", 6),
+                     ("a = \"\\UFFFFFFFF\"", 6),
+                     ("\"\\uD800\" = 1", 2),
+                     ("[\"\\uD800\"]", 3),
+                     ("a = { \"\\uD800\" = 1 }", 8)];
+        for &(text, pos) in cases.iter() {
+            let err = space_toml::parse(text).err().expect(text);
+            match err.kind {
+                ErrorKind::Lex(ref err) => {
+                    match err.kind {
+                        LexerErrorKind::InvalidUnicode { pos: found } => {
+                            assert_eq!(found, pos, "{:?}", text)
+                        }
+                        ref other => panic!("Unexpected lexer error: {:?}", other),
+                    }
+                }
+                ref other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        let text = "a = \"\\uD7FF\\uE000\\U0010FFFF\"\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        assert_eq!(document.root().get_str("a").unwrap(), "\u{D7FF}\u{E000}\u{10FFFF}");
+    }
+}
+
+#[cfg(feature = "units")]
+pub mod units {
+    use space_toml::{self, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn durations() {
+        let text = "a = \"30s\"\nb = '1h30m'\nc = \"1.5 ms\"\nd = \"2d 3us\"\n\
+                    e = \"5 weeks\"\nf = 30\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        let get = |key| root.get(key).and_then(Value::as_duration);
+        assert_eq!(get("a"), Some(Duration::from_secs(30)));
+        assert_eq!(get("b"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(get("c"), Some(Duration::new(0, 1_500_000)));
+        assert_eq!(get("d"), Some(Duration::new(2 * 24 * 60 * 60, 3_000)));
+        assert_eq!(get("e"), None);
+        assert_eq!(get("f"), None);
+        for text in vec!["", "s", "1..5s", "1h 30", "30 s s"] {
+            assert_eq!(Value::from(text).as_duration(), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn byte_sizes() {
+        let text = "a = \"10MiB\"\nb = '10MB'\nc = \"1.5 kib\"\nd = \"512\"\ne = 64\nf = -1\n";
+        let mut document = space_toml::parse(text).expect("Parsing failed");
+        let root = document.root();
+        let get = |key| root.get(key).and_then(Value::as_byte_size);
+        assert_eq!(get("a"), Some(10 * 1024 * 1024));
+        assert_eq!(get("b"), Some(10_000_000));
+        assert_eq!(get("c"), Some(1536));
+        assert_eq!(get("d"), Some(512));
+        assert_eq!(get("e"), Some(64));
+        assert_eq!(get("f"), None);
+        for text in vec!["", "MB", "10 MB 5 KB", "10 bits", "100000 PiB"] {
+            assert_eq!(Value::from(text).as_byte_size(), None, "{:?}", text);
+        }
+    }
+}
+
+pub mod generated {
+    //! Round-trip tests over randomly generated documents.
+    use space_toml;
+
+    /// A small pseudo-random number generator, so that every run tests the same cases.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+
+        fn chance(&mut self, percent: usize) -> bool {
+            self.below(100) < percent
+        }
+    }
+
+    const SPACES: &[&str] = &["", " ", " ", "  ", "\t"];
+    const COMMENTS: &[&str] = &["#", "# A comment", "#no space", "# Unicode: æøå", "#\t# twice"];
+
+    /// The generator state for one document.
+    struct Generator {
+        random: Random,
+        newline: &'static str,
+        keys: usize,
+        /// How many inline tables the generated value is in, which must fit on one line.
+        inline_tables: usize,
+    }
+
+    impl Generator {
+        fn space(&mut self) -> &'static str {
+            self.random.pick(SPACES)
+        }
+
+        /// Returns a new key, which is unique within the document.
+        fn key(&mut self) -> String {
+            self.keys += 1;
+            match self.random.below(4) {
+                0 => format!("\"quoted key {}\"", self.keys),
+                1 => format!("'literal.key{}'", self.keys),
+                2 => format!("snake_key_{}", self.keys),
+                _ => format!("key-{}", self.keys),
+            }
+        }
+
+        fn value(&mut self, depth: usize) -> String {
+            let kinds = if depth < 2 { 9 } else { 7 };
+            let kind = self.random.below(kinds);
+            self.value_of_kind(kind, depth)
+        }
+
+        fn value_of_kind(&mut self, kind: usize, depth: usize) -> String {
+            let kind = if kind == 5 && self.inline_tables > 0 { 3 } else { kind };
+            let random = &mut self.random;
+            match kind {
+                0 => random.pick(&["0", "42", "-17", "+99", "1_000", "-9_223_372_036_854_775_808"]),
+                1 => random.pick(&["3.14", "-0.01", "+1.5", "5e+22", "1e6", "6.626e-34", "9_2.5"]),
+                2 => random.pick(&["true", "false"]),
+                3 => random.pick(&["\"\"", "\"hello\"", "\"tab\\tnewline\\n\"", "\"\\u00e9\\\"\""]),
+                4 => random.pick(&["''", "'C:\\temp'", "'it \"quoted\"'"]),
+                5 => random.pick(&["\"\"\"\nline one\n  line two\"\"\"", "'''\nraw\\text'''"]),
+                6 => random.pick(&["1979-05-27T07:32:00Z", "1979-05-27T00:32:00.999999-07:00"]),
+                7 => return self.array(depth),
+                _ => return self.inline_table(depth),
+            }.to_string()
+        }
+
+        fn array(&mut self, depth: usize) -> String {
+            let kind = self.random.below(if depth < 2 { 9 } else { 7 });
+            let multiline = self.random.chance(30) && self.inline_tables == 0;
+            let count = self.random.below(4);
+            let mut out = String::from("[");
+            for i in 0..count {
+                if i > 0 {
+                    out.push(',');
+                }
+                if multiline {
+                    out.push_str(self.newline);
+                    out.push_str("  ");
+                } else {
+                    out.push_str(self.space());
+                }
+                out.push_str(&self.value_of_kind(kind, depth + 1));
+                out.push_str(self.space());
+            }
+            if multiline {
+                if count > 0 && self.random.chance(50) {
+                    out.push(',');
+                }
+                if self.random.chance(50) {
+                    out.push_str(" ");
+                    out.push_str(self.random.pick(COMMENTS));
+                }
+                out.push_str(self.newline);
+            }
+            out.push(']');
+            out
+        }
+
+        fn inline_table(&mut self, depth: usize) -> String {
+            let mut out = String::from("{");
+            self.inline_tables += 1;
+            for i in 0..self.random.below(4) {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(self.space());
+                out.push_str(&self.key());
+                out.push_str(self.space());
+                out.push('=');
+                out.push_str(self.space());
+                out.push_str(&self.value(depth + 1));
+                out.push_str(self.space());
+            }
+            self.inline_tables -= 1;
+            out.push('}');
+            out
+        }
+
+        /// Adds a few entry, comment and blank lines.
+        fn lines(&mut self, out: &mut String) {
+            for _ in 0..self.random.below(5) {
+                match self.random.below(6) {
+                    0 => {}
+                    1 => out.push_str(self.random.pick(COMMENTS)),
+                    _ => {
+                        out.push_str(self.random.pick(&["", "", "  ", "\t"]));
+                        out.push_str(&self.key());
+                        out.push_str(self.space());
+                        out.push('=');
+                        out.push_str(self.space());
+                        out.push_str(&self.value(0));
+                        if self.random.chance(30) {
+                            out.push_str(self.space());
+                            out.push_str(self.random.pick(COMMENTS));
+                        }
+                    }
+                }
+                out.push_str(self.newline);
+            }
+        }
+
+        fn header(&mut self, path: &str, array: bool, out: &mut String) {
+            out.push_str(if array { "[[" } else { "[" });
+            out.push_str(self.space());
+            out.push_str(path);
+            out.push_str(self.space());
+            out.push_str(if array { "]]" } else { "]" });
+            if self.random.chance(30) {
+                out.push_str(self.space());
+                out.push_str(self.random.pick(COMMENTS));
+            }
+            out.push_str(self.newline);
+        }
+
+        fn document(&mut self) -> String {
+            let mut out = String::new();
+            self.lines(&mut out);
+            for i in 0..self.random.below(4) {
+                let name = format!("table{}", i);
+                let array = self.random.chance(30);
+                for _ in 0..if array { 1 + self.random.below(3) } else { 1 } {
+                    self.header(&name, array, &mut out);
+                    self.lines(&mut out);
+                    if self.random.chance(30) {
+                        let path = format!("{} . \"sub table\"", name);
+                        self.header(&path, false, &mut out);
+                        self.lines(&mut out);
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..300 {
+            let mut generator = Generator {
+                random: Random(seed),
+                newline: if seed % 5 == 0 { "\r\n" } else { "\n" },
+                keys: 0,
+                inline_tables: 0,
+            };
+            let text = generator.document();
+            let document = match space_toml::parse(&text) {
+                Ok(document) => document,
+                Err(err) => panic!("Seed {}: parsing failed: {}\n{}", seed, err, text),
+            };
+            let mut out = String::new();
+            document.write(&mut out);
+            assert_eq!(out, text, "Seed {}: the document was not preserved", seed);
+            let reparsed = space_toml::parse(&out).expect("Reparsing failed");
+            let mut again = String::new();
+            reparsed.write(&mut again);
+            assert_eq!(again, out, "Seed {}: writing is not idempotent", seed);
+            assert!(document.diff(&reparsed).is_empty());
+        }
+    }
+}
+
+pub mod long_tokens {
+    use space_toml;
+    use space_toml::debug::{self, LineIndex};
+    use std::time::{Duration, Instant};
+
+    /// Returns how long it takes to lex, parse and write the text.
+    fn time_parse(text: &str) -> Duration {
+        let start = Instant::now();
+        for token in space_toml::tokens(text) {
+            token.expect("Lexing failed");
+        }
+        let mut out = String::new();
+        space_toml::parse(text).expect("Parsing failed").write(&mut out);
+        assert_eq!(out.len(), text.len());
+        start.elapsed()
+    }
+
+    /// Checks that reading a text with a single token ten times as long takes roughly ten
+    /// times as long, and not a hundred.
+    /// The tests using it depend on the timing of the machine, so they are ignored by
+    /// default and run with `cargo test -- --ignored`.
+    fn assert_linear<F: Fn(&str) -> String>(document: F) {
+        let small = time_parse(&document(&"x".repeat(1 << 20)));
+        let large = time_parse(&document(&"x".repeat(10 << 20)));
+        assert!(large < small * 30, "1MB took {:?}, but 10MB took {:?}", small, large);
+    }
+
+    #[test]
+    #[ignore]
+    fn huge_string() {
+        assert_linear(|text| format!("a = \"{}\"\n", text));
+    }
+
+    #[test]
+    #[ignore]
+    fn huge_comment() {
+        assert_linear(|text| format!("# {}\na = 1\n", text));
+    }
+
+    #[test]
+    fn line_index() {
+        let text = "a = 1\n\n[t]\u{e9} = 'x\u{e9}y'\r\nb = 2";
+        let index = LineIndex::new(text);
+        for (offset, _) in text.char_indices().chain(Some((text.len(), ' '))) {
+            assert_eq!(index.position(offset), debug::get_position(text, offset));
+        }
+        assert_eq!(index.position(7), (3, 1));
+    }
+}
+
+/// Runs every case of the toml-test suite in `tests/valid` and `tests/invalid` with the
+/// strict parse options: valid documents must parse, be written back unchanged and match
+/// their JSON file, while invalid documents must fail to parse. The cases that are known
+/// to fail are listed, so that the test fails both when a case breaks and when a listed
+/// case is fixed.
+pub mod conformance {
+    use std::fs;
+    use std::path::Path;
+    use std::panic;
+    use rustc_serialize::json::Json;
+    use space_toml::{self, ParseOptions};
+    use super::serialize_json;
+
+    /// Valid cases that are known to fail, with the reason.
+    const KNOWN_VALID_FAILURES: &'static [(&'static str, &'static str)] = &[
+        ("example-bom", "its values are in example.json, checked by valid::example_bom"),
+    ];
+
+    /// Invalid cases that are known to be accepted, with the reason.
+    const KNOWN_INVALID_FAILURES: &'static [(&'static str, &'static str)] = &[
+        // Local datetimes without an offset are valid since TOML 0.5
+        ("datetime-malformed-no-z", "the suite predates local datetimes"),
+        ("float-no-trailing-digits", "a float may end with its decimal point"),
+        ("key-after-array", "an entry may follow a header on the same line"),
+        ("key-after-table", "an entry may follow a header on the same line"),
+        ("table-whitespace", "keys in a header may be separated by whitespace"),
+    ];
+
+    /// Returns the names and texts of the files with the given extension in the given
+    /// directory of the suite, sorted by name.
+    fn cases(dir: &str, extension: &str) -> Vec<(String, String)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(dir);
+        let mut cases = fs::read_dir(dir)
+            .expect("Could not read the test directory")
+            .map(|entry| entry.expect("Could not read the test directory").path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == extension))
+            .map(|path| {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let text = fs::read_to_string(&path).expect("Could not read test file");
+                (name, text)
+            })
+            .collect::<Vec<_>>();
+        cases.sort();
+        cases
+    }
+
+    /// Checks a valid case, returning why it fails.
+    fn check_valid(toml: &str, json: Option<&str>) -> Result<(), String> {
+        let mut document = space_toml::parse_with(toml, &ParseOptions::strict())
+            .map_err(|err| err.message())?;
+        let mut out = String::new();
+        document.write(&mut out);
+        if out != toml {
+            return Err("the written document differs".to_string());
+        }
+        let json = match json {
+            Some(json) => Json::from_str(json).map_err(|err| err.to_string())?,
+            None => return Err("no JSON file".to_string()),
+        };
+        if serialize_json(&document.root()) != json {
+            return Err("the values differ from the JSON file".to_string());
+        }
+        Ok(())
+    }
+
+    /// Runs the given check, turning panics into failures.
+    fn run<F: FnOnce() -> Result<(), String> + panic::UnwindSafe>(check: F) -> Result<(), String> {
+        panic::catch_unwind(check).unwrap_or_else(|_| Err("panicked".to_string()))
+    }
+
+    /// Asserts that exactly the known cases failed.
+    fn assert_failures(failures: Vec<(String, String)>, known: &[(&str, &str)]) {
+        let unexpected = failures.iter()
+            .filter(|&&(ref name, _)| !known.iter().any(|&(known, _)| known == name))
+            .collect::<Vec<_>>();
+        let fixed = known.iter()
+            .filter(|&&(known, _)| !failures.iter().any(|&(ref name, _)| name == known))
+            .collect::<Vec<_>>();
+        assert!(unexpected.is_empty() && fixed.is_empty(),
+                "Unexpected failures: {:?}\nKnown failures that now pass: {:?}",
+                unexpected,
+                fixed);
+    }
+
+    #[test]
+    fn valid() {
+        let jsons = cases("valid", "json");
+        let mut failures = Vec::new();
+        for (name, toml) in cases("valid", "toml") {
+            let json = jsons.iter().find(|&&(ref other, _)| *other == name).map(|c| c.1.clone());
+            let result = run(move || check_valid(&toml, json.as_ref().map(|json| &json[..])));
+            if let Err(reason) = result {
+                failures.push((name, reason));
+            }
+        }
+        assert_failures(failures, KNOWN_VALID_FAILURES);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut failures = Vec::new();
+        for (name, toml) in cases("invalid", "toml") {
+            let result = run(move || match space_toml::parse_with(&toml, &ParseOptions::strict()) {
+                Ok(_) => Err("parsed without errors".to_string()),
+                Err(_) => Ok(()),
+            });
+            if let Err(reason) = result {
+                failures.push((name, reason));
+            }
+        }
+        assert_failures(failures, KNOWN_INVALID_FAILURES);
+    }
+}